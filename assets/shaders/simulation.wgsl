@@ -0,0 +1,989 @@
+fn hash(state: u32) -> u32 {
+    var s: u32 = state;
+    s = s ^ 2747636419u;
+    s = s * 2654435769u;
+    s = s ^ (s >> 16u);
+    s = s * 2654435769u;
+    s = s ^ (s >> 16u);
+    s = s * 2654435769u;
+    return s;
+}
+
+fn scaleToRange01(state: u32) -> f32 {
+    return f32(state) / 4294967295.0;
+}
+
+struct Agent {
+    position: vec2<f32>;
+    angle: f32;
+    species: i32;
+    age: f32;
+    energy: f32;
+};
+
+struct Settings {
+    trail_weight: f32;
+    self_follow: f32;
+    move_speed: f32;
+    turn_speed: f32;
+    sensor_angle_degrees: f32;
+    sensor_offset: f32;
+    sensor_size: i32;
+    food_weight: f32;
+    food_consumption: f32;
+    lifespan: f32;
+    energy_gain: f32;
+    energy_cost: f32;
+    split_energy: f32;
+    decay_rate: f32;
+    diffuse_rate: f32;
+};
+
+struct GlobalSettings {
+    decay_rate: f32;
+    diffuse_rate: f32;
+};
+
+struct AgentBuffer {
+    agents: array<Agent>;
+};
+
+struct AgentSettingsBuffer {
+    settings: array<Settings>;
+};
+
+struct FoodBuffer {
+    food: array<f32>;
+};
+
+struct InteractionBuffer {
+    weights: array<f32>;
+};
+
+// A move into another cell waiting for `resolve`, in occupancy mode.
+struct Move {
+    position: vec2<f32>;
+    deposit: u32;
+    pending: u32;
+};
+
+struct MoveBuffer {
+    moves: array<Move>;
+};
+
+// One flag per cell set by `occupy` for cells holding an agent, followed by
+// one claim per cell holding the highest `id + 1` of the agents moving in.
+struct OccupancyBuffer {
+    cells: array<atomic<u32>>;
+};
+
+// Trail deposited this step in `DEPOSIT_SCALE` fixed point, one map of
+// pixels per species. Agents add to it atomically, so deposits landing on
+// the same pixel all count.
+struct DepositBuffer {
+    deposits: array<atomic<u32>>;
+};
+
+// `DepositBuffer` for the passes reading it.
+struct DepositReadBuffer {
+    deposits: array<u32>;
+};
+
+// One side of a separable kernel per axis, `x[i]` weighs the pixels `i`
+// to the left and right. The single pass `blur` reads the whole kernel from
+// `full` instead, in rows 64 wide.
+struct KernelWeights {
+    radius: vec2<u32>;
+    x: array<f32, 32>;
+    y: array<f32, 32>;
+    full: array<f32, 4096>;
+};
+
+struct Time {
+    step: u32;
+    seed: u32;
+    delta: f32;
+    boundary: u32;
+    occupancy: u32;
+    deposition: u32;
+};
+
+struct Flow {
+    kind: u32;
+    speed: f32;
+    velocity: vec2<f32>;
+    center: vec2<f32>;
+    scale: f32;
+    evolution: f32;
+    agent_drift: f32;
+    trail_advection: f32;
+};
+
+struct FlowImage {
+    velocities: array<vec2<f32>>;
+};
+
+[[group(1), binding(0)]]
+var<uniform> time: Time;
+[[group(1), binding(1)]]
+var<uniform> flow: Flow;
+[[group(1), binding(2)]]
+var<storage, read> flow_image: FlowImage;
+
+let BOUNDARY_CLAMP: u32 = 0u;
+let BOUNDARY_WRAP: u32 = 1u;
+let BOUNDARY_REFLECT: u32 = 2u;
+
+// The texel read for `coords`, which may lie outside the map.
+fn boundary_coords(coords: vec2<i32>, dim: vec2<i32>) -> vec2<i32> {
+    if (time.boundary == BOUNDARY_WRAP) {
+        return (coords % dim + dim) % dim;
+    }
+    return clamp(coords, vec2<i32>(0), dim - vec2<i32>(1));
+}
+
+// Index of the cell `pos` lies in, positions on the far edges count as the
+// last cell.
+fn cell_index(pos: vec2<f32>, dim: vec2<u32>) -> u32 {
+    let cell = min(vec2<u32>(pos), dim - vec2<u32>(1u));
+    return cell.y * dim.x + cell.x;
+}
+
+fn wrap_position(pos: vec2<f32>, dim: vec2<f32>) -> vec2<f32> {
+    let wrapped = pos - dim * floor(pos / dim);
+    // Rounding can land tiny negative positions exactly on the far edge.
+    return select(wrapped, vec2<f32>(0.0), wrapped >= dim);
+}
+
+let DEPOSIT_SCALE: f32 = 65536.0;
+
+// Where in `DepositBuffer` a deposit at `pos` by `species` goes.
+fn deposit_index(pos: vec2<f32>, species: i32, dim: vec2<u32>) -> u32 {
+    return u32(species) * dim.x * dim.y + cell_index(pos, dim);
+}
+
+// The fixed point trail one agent deposits in a step. Sums past 65536 in a
+// single pixel and step wrap around.
+fn deposit_amount(trail_weight: f32) -> u32 {
+    return u32(max(0.0, trail_weight * time.delta) * DEPOSIT_SCALE + 0.5);
+}
+
+let DEPOSITION_POINT: u32 = 0u;
+let DEPOSITION_BILINEAR: u32 = 1u;
+let DEPOSITION_SWEPT: u32 = 2u;
+let SWEEP_MAX_SAMPLES: u32 = 64u;
+
+// Up to four deposits, as indices into `DepositBuffer` and amounts, that a
+// splat spreads over.
+struct Splat {
+    indices: array<u32, 4>;
+    amounts: array<u32, 4>;
+};
+
+// The move from `from` to `to`, the short way around on wrapping maps.
+fn deposit_path(from: vec2<f32>, to: vec2<f32>, dim: vec2<u32>) -> vec2<f32> {
+    let path = to - from;
+    if (time.boundary == BOUNDARY_WRAP) {
+        let dimf32 = vec2<f32>(dim);
+        return path - dimf32 * floor(path / dimf32 + vec2<f32>(0.5));
+    }
+    return path;
+}
+
+// How many splats deposit the move from `from` to `to`.
+fn deposit_samples(from: vec2<f32>, to: vec2<f32>, dim: vec2<u32>) -> u32 {
+    if (time.deposition != DEPOSITION_SWEPT) {
+        return 1u;
+    }
+    return min(SWEEP_MAX_SAMPLES, max(1u, u32(ceil(length(deposit_path(from, to, dim))))));
+}
+
+// Splat `sample` of `samples` depositing `amount` on the move from `from`
+// to `to`. The last one lands on `to` and the amounts add up exactly.
+fn deposit_splat(from: vec2<f32>, to: vec2<f32>, sample: u32, samples: u32, amount: u32, species: i32, dim: vec2<u32>) -> Splat {
+    var pos: vec2<f32> = to;
+    if (sample + 1u < samples) {
+        pos = from + deposit_path(from, to, dim) * f32(sample + 1u) / f32(samples);
+        if (time.boundary == BOUNDARY_WRAP) {
+            pos = wrap_position(pos, vec2<f32>(dim));
+        }
+    }
+    let total = f32(amount);
+    let share = u32(total * f32(sample + 1u) / f32(samples)) - u32(total * f32(sample) / f32(samples));
+
+    var splat: Splat;
+    if (time.deposition == DEPOSITION_POINT) {
+        splat.indices[0] = deposit_index(pos, species, dim);
+        splat.amounts[0] = share;
+        return splat;
+    }
+
+    // Bilinear weights of the four pixel centres around `pos`.
+    let dimi32 = vec2<i32>(dim);
+    let source = pos - vec2<f32>(0.5);
+    let base = floor(source);
+    let f = source - base;
+    let texel = vec2<i32>(base);
+    splat.indices[0] = deposit_index(vec2<f32>(boundary_coords(texel, dimi32)), species, dim);
+    splat.indices[1] = deposit_index(vec2<f32>(boundary_coords(texel + vec2<i32>(1, 0), dimi32)), species, dim);
+    splat.indices[2] = deposit_index(vec2<f32>(boundary_coords(texel + vec2<i32>(0, 1), dimi32)), species, dim);
+    splat.indices[3] = deposit_index(vec2<f32>(boundary_coords(texel + vec2<i32>(1, 1), dimi32)), species, dim);
+    let part = f32(share);
+    splat.amounts[0] = u32(part * ((1.0 - f.x) * (1.0 - f.y)));
+    splat.amounts[1] = u32(part * (f.x * (1.0 - f.y)));
+    splat.amounts[2] = u32(part * ((1.0 - f.x) * f.y));
+    splat.amounts[3] = share - min(share, splat.amounts[0] + splat.amounts[1] + splat.amounts[2]);
+    return splat;
+}
+
+
+let FLOW_NONE: u32 = 0u;
+let FLOW_UNIFORM: u32 = 1u;
+let FLOW_VORTEX: u32 = 2u;
+let FLOW_CURL_NOISE: u32 = 3u;
+let FLOW_IMAGE: u32 = 4u;
+
+// Random value in [-1, 1] for a corner of the noise lattice.
+fn lattice(cell: vec3<i32>) -> f32 {
+    return scaleToRange01(hash(u32(cell.x) ^ hash(u32(cell.y) ^ hash(u32(cell.z) ^ time.seed)))) * 2.0 - 1.0;
+}
+
+fn value_noise(p: vec3<f32>) -> f32 {
+    let cell = floor(p);
+    let f = p - cell;
+    let u = f * f * (3.0 - 2.0 * f);
+    let c = vec3<i32>(cell);
+    let x00 = lattice(c) * (1.0 - u.x) + lattice(c + vec3<i32>(1, 0, 0)) * u.x;
+    let x10 = lattice(c + vec3<i32>(0, 1, 0)) * (1.0 - u.x) + lattice(c + vec3<i32>(1, 1, 0)) * u.x;
+    let x01 = lattice(c + vec3<i32>(0, 0, 1)) * (1.0 - u.x) + lattice(c + vec3<i32>(1, 0, 1)) * u.x;
+    let x11 = lattice(c + vec3<i32>(0, 1, 1)) * (1.0 - u.x) + lattice(c + vec3<i32>(1, 1, 1)) * u.x;
+    let y0 = x00 * (1.0 - u.y) + x10 * u.y;
+    let y1 = x01 * (1.0 - u.y) + x11 * u.y;
+    return y0 * (1.0 - u.z) + y1 * u.z;
+}
+
+// The curl of value noise, which swirls without sources or sinks.
+fn curl_noise(pos: vec2<f32>) -> vec2<f32> {
+    let t = f32(time.step) * time.delta * flow.evolution;
+    let p = vec3<f32>(pos / flow.scale, t);
+    let e = 0.01;
+    let dx = value_noise(p + vec3<f32>(e, 0.0, 0.0)) - value_noise(p - vec3<f32>(e, 0.0, 0.0));
+    let dy = value_noise(p + vec3<f32>(0.0, e, 0.0)) - value_noise(p - vec3<f32>(0.0, e, 0.0));
+    return vec2<f32>(dy, -dx) / (2.0 * e);
+}
+
+// Velocity of the flow at `pos`, in pixels per second.
+fn flow_at(pos: vec2<f32>, dim: vec2<u32>) -> vec2<f32> {
+    if (flow.kind == FLOW_UNIFORM) {
+        return flow.velocity;
+    }
+    if (flow.kind == FLOW_VORTEX) {
+        let d = pos - flow.center;
+        let r2 = dot(d, d) / (flow.scale * flow.scale);
+        return vec2<f32>(-d.y, d.x) / flow.scale * flow.speed * exp(0.5 - 0.5 * r2);
+    }
+    if (flow.kind == FLOW_CURL_NOISE) {
+        return curl_noise(pos) * flow.speed;
+    }
+    if (flow.kind == FLOW_IMAGE) {
+        let cell = cell_index(pos, dim);
+        if (cell < arrayLength(&flow_image.velocities)) {
+            return flow_image.velocities[cell] * flow.speed;
+        }
+    }
+    return vec2<f32>(0.0);
+}
+
+
+[[group(0), binding(0)]]
+var<storage, read_write> m_agents: AgentBuffer;
+[[group(0), binding(1)]]
+var<storage, read> m_agent_settings: AgentSettingsBuffer;
+[[group(0), binding(2)]]
+var m_texture_r: texture_storage_2d_array<rgba16float, read>;
+[[group(0), binding(3)]]
+var<storage, read_write> m_deposits: DepositBuffer;
+[[group(0), binding(4)]]
+var m_obstacles: texture_2d<f32>;
+[[group(0), binding(5)]]
+var<storage, read> m_food: FoodBuffer;
+[[group(0), binding(6)]]
+var<storage, read> m_interactions: InteractionBuffer;
+[[group(0), binding(7)]]
+var<storage, read_write> m_occupancy: OccupancyBuffer;
+[[group(0), binding(8)]]
+var<storage, read_write> m_moves: MoveBuffer;
+
+// How strongly species `from` follows the trail of species `to`.
+fn interaction(from: i32, to: i32, species_count: i32) -> f32 {
+    if (to >= species_count) {
+        return 0.0;
+    }
+    return m_interactions.weights[from * species_count + to];
+}
+
+fn obstacle_at(pos: vec2<f32>) -> f32 {
+    let dim = vec2<i32>(textureDimensions(m_obstacles));
+    return textureLoad(m_obstacles, clamp(vec2<i32>(pos), vec2<i32>(0), dim - vec2<i32>(1)), 0).r;
+}
+
+fn sense(agent: Agent, sensor_angle_offset: f32) -> f32 {
+    let settings = m_agent_settings.settings[agent.species];
+
+    let sensor_angle = agent.angle + sensor_angle_offset;
+    let sensor_dir = vec2<f32>(cos(sensor_angle), sin(sensor_angle));
+
+    let sensor_pos = agent.position + sensor_dir * settings.sensor_offset;
+    let sensor_center = vec2<i32>(sensor_pos);
+
+    var sum: vec4<f32> = vec4<f32>(0.0);
+
+    let dim = vec2<i32>(textureDimensions(m_texture_r));
+    let sensor_size = settings.sensor_size;
+
+    let species_count = i32(arrayLength(&m_agent_settings.settings));
+
+    for (var species: i32 = 0; species < species_count; species = species + 4) {
+        let mask = vec4<f32>(
+            interaction(agent.species, species + 0, species_count),
+            interaction(agent.species, species + 1, species_count),
+            interaction(agent.species, species + 2, species_count),
+            interaction(agent.species, species + 3, species_count)
+        );
+        for (var offset_x: i32 = -sensor_size; offset_x <= sensor_size; offset_x = offset_x + 1) {
+            for (var offset_y: i32 = -sensor_size; offset_y <= sensor_size; offset_y = offset_y + 1) {
+                let offset = vec2<i32>(offset_x, offset_y);
+                let sample = boundary_coords(sensor_center + offset, dim);
+                sum = sum + mask * textureLoad(m_texture_r, sample, species / 4);
+            }
+        }
+    }
+
+    var food: f32 = 0.0;
+    if (settings.food_weight != 0.0) {
+        for (var offset_x: i32 = -sensor_size; offset_x <= sensor_size; offset_x = offset_x + 1) {
+            for (var offset_y: i32 = -sensor_size; offset_y <= sensor_size; offset_y = offset_y + 1) {
+                let offset = vec2<i32>(offset_x, offset_y);
+                let sample = boundary_coords(sensor_center + offset, dim);
+                food = food + m_food.food[sample.y * dim.x + sample.x];
+            }
+        }
+    }
+
+    return sum.x + sum.y + sum.z + sum.w + food * settings.food_weight;
+}
+
+[[stage(compute), workgroup_size(32)]]
+fn update(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+    let id = global_id.x;
+    let agent_count = arrayLength(&m_agents.agents);
+
+    if (id >= agent_count) {
+        return;
+    }
+
+    let dim = vec2<u32>(textureDimensions(m_texture_r));
+
+    let agent = m_agents.agents[id];
+    // Dead slot
+    if (agent.species < 0) {
+        return;
+    }
+    let settings = m_agent_settings.settings[agent.species];
+    let pos = agent.position;
+
+    var random: u32 = hash(u32(pos.y) * dim.x + u32(pos.x) + hash(id + hash(time.seed ^ hash(time.step))));
+
+    let sensor_angle_rad = settings.sensor_angle_degrees * (3.1415 / 180.0);
+    let weight_forward = sense(agent, 0.0);
+    let weight_left = sense(agent, sensor_angle_rad);
+    let weight_right = sense(agent, -sensor_angle_rad);
+
+    let random_steer_strength = scaleToRange01(random);
+
+    let turn_amount = settings.turn_speed * time.delta;
+
+    // Continue in same direction
+    if (weight_forward > weight_left && weight_forward > weight_right) {
+        m_agents.agents[id].angle = agent.angle + 0.0;
+    }
+    else if (weight_forward < weight_left && weight_forward < weight_right) {
+        m_agents.agents[id].angle = agent.angle + (random_steer_strength - 0.5) * 2.0 * turn_amount;
+    }
+    // Turn right
+    else if (weight_right > weight_left) {
+        m_agents.agents[id].angle = agent.angle - random_steer_strength * turn_amount;
+    }
+    // Turn left
+    else if (weight_left > weight_right) {
+        m_agents.agents[id].angle = agent.angle + random_steer_strength * turn_amount;
+    }
+
+    let dist = time.delta * settings.move_speed;
+    let dir = vec2<f32>(cos(agent.angle), sin(agent.angle));
+    let drift = flow_at(pos, dim) * flow.agent_drift * time.delta;
+    var new_pos: vec2<f32> = agent.position + dist * dir + drift;
+
+    let dimf32 = vec2<f32>(dim);
+    var deposit: bool = false;
+    var outside: bool = new_pos.x < 0.0 || new_pos.x >= dimf32.x || new_pos.y < 0.0 || new_pos.y >= dimf32.y;
+    if (outside && time.boundary == BOUNDARY_WRAP) {
+        new_pos = wrap_position(new_pos, dimf32);
+        outside = false;
+    }
+
+    // Stay put and mirror the heading on the edges that were crossed
+    if (outside && time.boundary == BOUNDARY_REFLECT) {
+        var reflected: vec2<f32> = dir;
+        if (new_pos.x < 0.0 || new_pos.x >= dimf32.x) {
+            reflected.x = -reflected.x;
+        }
+        if (new_pos.y < 0.0 || new_pos.y >= dimf32.y) {
+            reflected.y = -reflected.y;
+        }
+        new_pos = pos;
+        m_agents.agents[id].angle = atan2(reflected.y, reflected.x);
+    }
+    // Clamp position to map boundaries, and pick new random move dir if hit boundary
+    else if (outside) {
+        random = hash(random);
+        let random_angle = scaleToRange01(random) * 2.0 * 3.1415;
+
+        new_pos = clamp(new_pos, vec2<f32>(0.0), dimf32);
+        if (obstacle_at(new_pos) > obstacle_at(pos)) {
+            new_pos = pos;
+        }
+        m_agents.agents[id].angle = random_angle;
+    }
+    // Turn away from walls instead of entering them. Agents that started
+    // inside a wall may walk out of it.
+    else if (obstacle_at(new_pos) > obstacle_at(pos)) {
+        random = hash(random);
+        let random_angle = scaleToRange01(random) * 2.0 * 3.1415;
+
+        new_pos = pos;
+        m_agents.agents[id].angle = random_angle;
+    }
+    else {
+        deposit = true;
+    }
+
+    // In occupancy mode moves into another cell only happen in `resolve`,
+    // once every agent has claimed its cell.
+    let cell = cell_index(new_pos, dim);
+    if (time.occupancy != 0u && cell != cell_index(pos, dim)) {
+        m_moves.moves[id] = Move(new_pos, u32(deposit), 1u);
+        if (atomicLoad(&m_occupancy.cells[cell]) == 0u) {
+            atomicMax(&m_occupancy.cells[dim.x * dim.y + cell], id + 1u);
+        }
+    } else {
+        if (deposit) {
+            let amount = deposit_amount(settings.trail_weight);
+            let samples = deposit_samples(pos, new_pos, dim);
+            for (var sample: u32 = 0u; sample < samples; sample = sample + 1u) {
+                var splat: Splat = deposit_splat(pos, new_pos, sample, samples, amount, agent.species, dim);
+                for (var i: i32 = 0; i < 4; i = i + 1) {
+                    if (splat.amounts[i] != 0u) {
+                        let index = splat.indices[i];
+                        atomicAdd(&m_deposits.deposits[index], splat.amounts[i]);
+                    }
+                }
+            }
+        }
+        m_agents.agents[id].position = new_pos;
+    }
+
+    let age = agent.age + time.delta;
+    let energy = agent.energy + (settings.energy_gain * max(weight_forward, 0.0) - settings.energy_cost) * time.delta;
+    m_agents.agents[id].age = age;
+    m_agents.agents[id].energy = energy;
+    if ((settings.lifespan > 0.0 && age > settings.lifespan) || (settings.energy_cost > 0.0 && energy <= 0.0)) {
+        m_agents.agents[id].species = -1;
+    }
+}
+
+[[group(0), binding(0)]]
+var<storage, read_write> o_agents: AgentBuffer;
+[[group(0), binding(1)]]
+var<storage, read_write> o_moves: MoveBuffer;
+[[group(0), binding(2)]]
+var<storage, read_write> o_occupancy: OccupancyBuffer;
+[[group(0), binding(3)]]
+var<storage, read> o_agent_settings: AgentSettingsBuffer;
+[[group(0), binding(4)]]
+var<storage, read_write> o_deposits: DepositBuffer;
+[[group(0), binding(5)]]
+var o_obstacles: texture_2d<f32>;
+
+// Marks the cells holding a living agent, on a cleared `o_occupancy`.
+[[stage(compute), workgroup_size(32)]]
+fn occupy(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+    let id = global_id.x;
+    if (id >= arrayLength(&o_agents.agents)) {
+        return;
+    }
+    let agent = o_agents.agents[id];
+    if (agent.species >= 0) {
+        let dim = vec2<u32>(textureDimensions(o_obstacles));
+        atomicStore(&o_occupancy.cells[cell_index(agent.position, dim)], 1u);
+    }
+}
+
+// Moves every agent that won the claim on its free target cell. The others
+// stay put and pick a new random heading.
+[[stage(compute), workgroup_size(32)]]
+fn resolve(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+    let id = global_id.x;
+    if (id >= arrayLength(&o_agents.agents)) {
+        return;
+    }
+    let pending_move = o_moves.moves[id];
+    if (pending_move.pending == 0u) {
+        return;
+    }
+    o_moves.moves[id].pending = 0u;
+    let agent = o_agents.agents[id];
+    if (agent.species < 0) {
+        return;
+    }
+
+    let dim = vec2<u32>(textureDimensions(o_obstacles));
+    let cell = cell_index(pending_move.position, dim);
+    if (atomicLoad(&o_occupancy.cells[dim.x * dim.y + cell]) == id + 1u) {
+        if (pending_move.deposit != 0u) {
+            let settings = o_agent_settings.settings[agent.species];
+            let amount = deposit_amount(settings.trail_weight);
+            let samples = deposit_samples(agent.position, pending_move.position, dim);
+            for (var sample: u32 = 0u; sample < samples; sample = sample + 1u) {
+                var splat: Splat = deposit_splat(agent.position, pending_move.position, sample, samples, amount, agent.species, dim);
+                for (var i: i32 = 0; i < 4; i = i + 1) {
+                    if (splat.amounts[i] != 0u) {
+                        let index = splat.indices[i];
+                        atomicAdd(&o_deposits.deposits[index], splat.amounts[i]);
+                    }
+                }
+            }
+        }
+        o_agents.agents[id].position = pending_move.position;
+    } else {
+        let random = hash(hash(id + hash(time.seed ^ hash(time.step))) + 1u);
+        o_agents.agents[id].angle = scaleToRange01(random) * 2.0 * 3.1415;
+    }
+}
+
+[[group(0), binding(0)]]
+var<storage, read> l_agents: AgentBuffer;
+[[group(0), binding(1)]]
+var<storage, read_write> l_next_agents: AgentBuffer;
+[[group(0), binding(2)]]
+var<storage, read> l_agent_settings: AgentSettingsBuffer;
+
+fn can_split(agent: Agent) -> bool {
+    let split_energy = l_agent_settings.settings[agent.species].split_energy;
+    return split_energy > 0.0 && agent.energy >= split_energy;
+}
+
+// Each step pairs every slot with `id ^ mask` for a random `mask`, so a
+// dead slot has at most one parent and every invocation only writes its
+// own slot.
+fn partner_mask(count: u32) -> u32 {
+    var size: u32 = 1u;
+    loop {
+        if (size >= count) {
+            break;
+        }
+        size = size * 2u;
+    }
+    return 1u + hash(time.seed ^ hash(time.step + 1u)) % max(size - 1u, 1u);
+}
+
+// Splits agents with enough energy into their partner slot, if it is dead.
+// Reads `l_agents` and writes `l_next_agents`, which is then copied back.
+[[stage(compute), workgroup_size(32)]]
+fn lifecycle(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+) {
+    let id = global_id.x;
+    let agent_count = arrayLength(&l_agents.agents);
+    if (id >= agent_count) {
+        return;
+    }
+
+    var agent: Agent = l_agents.agents[id];
+    let partner_id = id ^ partner_mask(agent_count);
+    if (partner_id < agent_count) {
+        let partner = l_agents.agents[partner_id];
+        if (agent.species < 0 && partner.species >= 0 && can_split(partner)) {
+            agent = partner;
+            agent.angle = scaleToRange01(hash(id + hash(time.seed ^ hash(time.step)))) * 2.0 * 3.1415;
+            agent.age = 0.0;
+            agent.energy = partner.energy * 0.5;
+        } else if (agent.species >= 0 && partner.species < 0 && can_split(agent)) {
+            agent.energy = agent.energy * 0.5;
+        }
+    }
+    l_next_agents.agents[id] = agent;
+}
+
+[[group(0), binding(0)]]
+var<uniform> b_settings: GlobalSettings;
+
+[[group(0), binding(1)]]
+var b_texture_r: texture_storage_2d_array<rgba16float, read>;
+[[group(0), binding(2)]]
+var<storage, read> b_deposits: DepositReadBuffer;
+[[group(0), binding(3)]]
+var b_texture_w: texture_storage_2d_array<rgba16float, write>;
+[[group(0), binding(4)]]
+var b_obstacles: texture_2d<f32>;
+[[group(0), binding(5)]]
+var<storage, read> b_agent_settings: AgentSettingsBuffer;
+[[group(0), binding(6)]]
+var<storage, read> b_kernel: KernelWeights;
+[[group(0), binding(7)]]
+var b_blurred_r: texture_storage_2d_array<rgba32float, read>;
+[[group(0), binding(8)]]
+var b_blurred_w: texture_storage_2d_array<rgba32float, write>;
+
+// The species' own rates, or the global ones where they are negative or the
+// species doesn't exist.
+fn decay_rate(species: i32) -> f32 {
+    if (species < i32(arrayLength(&b_agent_settings.settings))) {
+        let rate = b_agent_settings.settings[species].decay_rate;
+        if (rate >= 0.0) {
+            return rate;
+        }
+    }
+    return b_settings.decay_rate;
+}
+
+fn diffuse_rate(species: i32) -> f32 {
+    if (species < i32(arrayLength(&b_agent_settings.settings))) {
+        let rate = b_agent_settings.settings[species].diffuse_rate;
+        if (rate >= 0.0) {
+            return rate;
+        }
+    }
+    return b_settings.diffuse_rate;
+}
+
+// Trail deposited at `coords` this step, `species` must exist.
+fn painted(coords: vec2<i32>, species: i32) -> f32 {
+    let dim = vec2<u32>(textureDimensions(b_obstacles));
+    let index = deposit_index(vec2<f32>(coords), species, dim);
+    return f32(b_deposits.deposits[index]) / DEPOSIT_SCALE;
+}
+
+fn fetch_color(coords: vec2<i32>, index: i32) -> vec4<f32> {
+    let dim = vec2<u32>(textureDimensions(b_obstacles));
+    let species_count = i32(arrayLength(&b_deposits.deposits) / (dim.x * dim.y));
+    var sum: vec4<f32> = textureLoad(b_texture_r, coords, index);
+    let species = index * 4;
+    sum = sum + vec4<f32>(painted(coords, species + 0), 0.0, 0.0, 0.0);
+    if (species + 1 >= species_count) { return sum; }
+    sum = sum + vec4<f32>(0.0, painted(coords, species + 1), 0.0, 0.0);
+    if (species + 2 >= species_count) { return sum; }
+    sum = sum + vec4<f32>(0.0, 0.0, painted(coords, species + 2), 0.0);
+    if (species + 3 >= species_count) { return sum; }
+    sum = sum + vec4<f32>(0.0, 0.0, 0.0, painted(coords, species + 3));
+    return sum;
+}
+
+// The trail the flow carries into `coords` this step, interpolated from
+// upstream.
+fn advected_color(coords: vec2<i32>, index: i32) -> vec4<f32> {
+    if (flow.kind == FLOW_NONE || flow.trail_advection == 0.0) {
+        return fetch_color(coords, index);
+    }
+    let dim = vec2<i32>(textureDimensions(b_obstacles));
+    let center = vec2<f32>(coords) + vec2<f32>(0.5);
+    let source = center - flow_at(center, vec2<u32>(dim)) * flow.trail_advection * time.delta - vec2<f32>(0.5);
+    let base = floor(source);
+    let f = source - base;
+    let texel = vec2<i32>(base);
+    let c00 = fetch_color(boundary_coords(texel, dim), index);
+    let c10 = fetch_color(boundary_coords(texel + vec2<i32>(1, 0), dim), index);
+    let c01 = fetch_color(boundary_coords(texel + vec2<i32>(0, 1), dim), index);
+    let c11 = fetch_color(boundary_coords(texel + vec2<i32>(1, 1), dim), index);
+    let top = c00 * (1.0 - f.x) + c10 * f.x;
+    let bottom = c01 * (1.0 - f.x) + c11 * f.x;
+    return top * (1.0 - f.y) + bottom * f.y;
+}
+
+// Mixes the diffused `mean` into the trail at `coords` and decays it.
+fn mix_and_decay(coords: vec2<i32>, species_group_id: i32, mean: vec4<f32>) {
+    // One rate per species packed into the layer
+    let species = species_group_id * 4;
+    let decay = vec4<f32>(decay_rate(species), decay_rate(species + 1), decay_rate(species + 2), decay_rate(species + 3));
+    let diffuse = vec4<f32>(diffuse_rate(species), diffuse_rate(species + 1), diffuse_rate(species + 2), diffuse_rate(species + 3));
+
+    let diffuse_weight = clamp(diffuse * time.delta, vec4<f32>(0.0), vec4<f32>(1.0));
+
+    let original_color = advected_color(coords, species_group_id);
+    let blurred_color = original_color * (1.0 - diffuse_weight) + mean * diffuse_weight;
+
+    let out = max(vec4<f32>(0.0), blurred_color - decay * time.delta);
+    textureStore(b_texture_w, coords, species_group_id, out);
+}
+
+[[stage(compute), workgroup_size(32, 32)]]
+fn blur(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+) {
+    let species_group_id = i32(id.z);
+
+    let dimensions = vec2<u32>(textureDimensions(b_texture_w));
+    if (id.x < 0u || id.x >= dimensions.x || id.y < 0u || id.y >= dimensions.y) {
+        return;
+    }
+    let coords = vec2<i32>(id.xy);
+    let dim = vec2<i32>(dimensions);
+
+    if (textureLoad(b_obstacles, coords, 0).r > 0.0) {
+        textureStore(b_texture_w, coords, species_group_id, vec4<f32>(0.0));
+        return;
+    }
+
+    // Walls take no part in the mean, so trail doesn't spread through them.
+    let radius = vec2<i32>(min(b_kernel.radius, vec2<u32>(31u)));
+    var sum: vec4<f32> = vec4<f32>(0.0);
+    var open_sum: f32 = 0.0;
+    for (var offset_x: i32 = -radius.x; offset_x <= radius.x; offset_x = offset_x + 1) {
+        for (var offset_y: i32 = -radius.y; offset_y <= radius.y; offset_y = offset_y + 1) {
+            let offset = vec2<i32>(offset_x, offset_y);
+            let sample = boundary_coords(coords + offset, dim);
+            let weight_index = (offset_y + radius.y) * 64 + offset_x + radius.x;
+            let open = (1.0 - textureLoad(b_obstacles, sample, 0).r) * b_kernel.full[weight_index];
+            sum = sum + advected_color(sample, species_group_id) * open;
+            open_sum = open_sum + open;
+        }
+    }
+
+    mix_and_decay(coords, species_group_id, sum / open_sum);
+}
+
+// Pixels `blur_x` and `blur_y` cover per workgroup, along their axis.
+let BLUR_TILE: i32 = 256;
+
+// The workgroup's row or column plus up to 31 pixels of kernel on both
+// sides, premultiplied by how open they are.
+var<workgroup> b_tile: array<vec4<f32>, 318>;
+var<workgroup> b_tile_open: array<f32, 318>;
+
+// Weighted sum of the tile around `local`, divided by the weight that
+// isn't covered by walls. Walls themselves come out as zero.
+fn tile_mean(local: i32, radius: i32, weights_y: bool) -> vec4<f32> {
+    var sum: vec4<f32> = vec4<f32>(0.0);
+    var open_sum: f32 = 0.0;
+    for (var i: i32 = -radius; i <= radius; i = i + 1) {
+        var weight: f32 = b_kernel.x[abs(i)];
+        if (weights_y) {
+            weight = b_kernel.y[abs(i)];
+        }
+        sum = sum + b_tile[local + radius + i] * weight;
+        open_sum = open_sum + b_tile_open[local + radius + i] * weight;
+    }
+    if (b_tile_open[local + radius] < 1.0) {
+        return vec4<f32>(0.0);
+    }
+    return sum / open_sum;
+}
+
+// First half of a separable kernel, along x into `b_blurred_w`.
+[[stage(compute), workgroup_size(256)]]
+fn blur_x(
+    [[builtin(workgroup_id)]] group: vec3<u32>,
+    [[builtin(local_invocation_index)]] local_index: u32,
+) {
+    let dim = vec2<i32>(textureDimensions(b_blurred_w));
+    let layer = i32(group.z);
+    let local = i32(local_index);
+    let start = vec2<i32>(i32(group.x) * BLUR_TILE, i32(group.y));
+    let radius = i32(min(b_kernel.radius.x, 31u));
+
+    for (var i: i32 = local; i < BLUR_TILE + 2 * radius; i = i + BLUR_TILE) {
+        let sample = boundary_coords(start + vec2<i32>(i - radius, 0), dim);
+        let open = 1.0 - textureLoad(b_obstacles, sample, 0).r;
+        b_tile[i] = advected_color(sample, layer) * open;
+        b_tile_open[i] = open;
+    }
+    workgroupBarrier();
+
+    let coords = start + vec2<i32>(local, 0);
+    if (coords.x >= dim.x) {
+        return;
+    }
+    textureStore(b_blurred_w, coords, layer, tile_mean(local, radius, false));
+}
+
+// Second half of a separable kernel, along y from `b_blurred_r`, then mixed
+// into the trail like `blur` does.
+[[stage(compute), workgroup_size(1, 256)]]
+fn blur_y(
+    [[builtin(workgroup_id)]] group: vec3<u32>,
+    [[builtin(local_invocation_index)]] local_index: u32,
+) {
+    let dim = vec2<i32>(textureDimensions(b_blurred_r));
+    let layer = i32(group.z);
+    let local = i32(local_index);
+    let start = vec2<i32>(i32(group.x), i32(group.y) * BLUR_TILE);
+    let radius = i32(min(b_kernel.radius.y, 31u));
+
+    for (var i: i32 = local; i < BLUR_TILE + 2 * radius; i = i + BLUR_TILE) {
+        let sample = boundary_coords(start + vec2<i32>(0, i - radius), dim);
+        let open = 1.0 - textureLoad(b_obstacles, sample, 0).r;
+        b_tile[i] = textureLoad(b_blurred_r, sample, layer) * open;
+        b_tile_open[i] = open;
+    }
+    workgroupBarrier();
+
+    let coords = start + vec2<i32>(0, local);
+    if (coords.y >= dim.y) {
+        return;
+    }
+    if (textureLoad(b_obstacles, coords, 0).r > 0.0) {
+        textureStore(b_texture_w, coords, layer, vec4<f32>(0.0));
+        return;
+    }
+    mix_and_decay(coords, layer, tile_mean(local, radius, true));
+}
+
+[[group(0), binding(0)]]
+var<storage, read> e_agent_settings: AgentSettingsBuffer;
+[[group(0), binding(1)]]
+var<storage, read> e_deposits: DepositReadBuffer;
+[[group(0), binding(2)]]
+var<storage, read_write> e_food: FoodBuffer;
+[[group(0), binding(3)]]
+var e_obstacles: texture_2d<f32>;
+
+// Agents of a species with `food_consumption` eat from every pixel they
+// deposited on this step. Runs between `blur` and clearing the deposits.
+[[stage(compute), workgroup_size(32, 32)]]
+fn eat(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+) {
+    let dimensions = vec2<u32>(textureDimensions(e_obstacles));
+    if (id.x >= dimensions.x || id.y >= dimensions.y) {
+        return;
+    }
+    let coords = vec2<i32>(id.xy);
+    let cell = id.y * dimensions.x + id.x;
+    let species_count = i32(arrayLength(&e_agent_settings.settings));
+
+    var food: f32 = e_food.food[cell];
+    for (var species: i32 = 0; species < species_count; species = species + 1) {
+        if (e_deposits.deposits[deposit_index(vec2<f32>(coords), species, dimensions)] > 0u) {
+            food = max(0.0, food - e_agent_settings.settings[species].food_consumption * time.delta);
+        }
+    }
+    e_food.food[cell] = food;
+}
+
+struct BrushStroke {
+    from: vec2<f32>;
+    to: vec2<f32>;
+    radius: f32;
+    softness: f32;
+    amount: f32;
+    species: i32;
+};
+
+struct BrushStrokeBuffer {
+    strokes: array<BrushStroke>;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> p_strokes: BrushStrokeBuffer;
+[[group(0), binding(1)]]
+var p_texture_r: texture_storage_2d_array<rgba16float, read>;
+[[group(0), binding(2)]]
+var p_texture_w: texture_storage_2d_array<rgba16float, write>;
+
+[[stage(compute), workgroup_size(32, 32)]]
+fn brush(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+) {
+    let dimensions = vec2<u32>(textureDimensions(p_texture_w));
+    if (id.x >= dimensions.x || id.y >= dimensions.y) {
+        return;
+    }
+    let coords = vec2<i32>(id.xy);
+    let layer = i32(id.z);
+    let pos = vec2<f32>(coords) + vec2<f32>(0.5);
+
+    var value: vec4<f32> = textureLoad(p_texture_r, coords, layer);
+    let stroke_count = arrayLength(&p_strokes.strokes);
+    for (var i: u32 = 0u; i < stroke_count; i = i + 1u) {
+        let stroke = p_strokes.strokes[i];
+        // Distance to the closest point on the segment the brush moved along.
+        let along = stroke.to - stroke.from;
+        let t = clamp(dot(pos - stroke.from, along) / max(dot(along, along), 0.0001), 0.0, 1.0);
+        let dist = distance(pos, stroke.from + along * t);
+        let edge = max(stroke.radius * stroke.softness, 0.0001);
+        let weight = clamp((stroke.radius - dist) / edge, 0.0, 1.0);
+
+        let species = vec4<i32>(layer * 4) + vec4<i32>(0, 1, 2, 3);
+        var mask: vec4<f32> = vec4<f32>(vec4<i32>(species == vec4<i32>(stroke.species)));
+        if (stroke.species < 0) {
+            mask = vec4<f32>(1.0);
+        }
+        value = clamp(value + mask * stroke.amount * weight, vec4<f32>(0.0), vec4<f32>(1.0));
+    }
+    textureStore(p_texture_w, coords, layer, value);
+}
+
+struct DispSettings {
+    color: vec3<f32>;
+    weight: f32;
+};
+
+struct DispSettingsBuffer {
+    settings: array<DispSettings>;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> c_disp_settings: DispSettingsBuffer;
+[[group(0), binding(1)]]
+var c_texture: texture_storage_2d_array<rgba16float, read>;
+[[group(0), binding(2)]]
+var c_disp_texture: texture_storage_2d<rgba8unorm, write>;
+
+[[stage(compute), workgroup_size(32, 32)]]
+fn combine(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+) {
+    let pos = vec2<i32>(id.xy);
+    let species_count = i32(arrayLength(&c_disp_settings.settings));
+    var col: vec3<f32> = vec3<f32>(0.0);
+    for(var i: i32 = 0; i < species_count / 4; i = i + 1) {
+        let vals = textureLoad(c_texture, pos, i);
+        col = col + c_disp_settings.settings[i * 4 + 0].color * vals.x;
+        col = col + c_disp_settings.settings[i * 4 + 1].color * vals.y;
+        col = col + c_disp_settings.settings[i * 4 + 2].color * vals.z;
+        col = col + c_disp_settings.settings[i * 4 + 3].color * vals.w;
+    }
+    let completed = (species_count / 4) * 4;
+    if (species_count % 4 != 0) {
+        let vals = textureLoad(c_texture, pos, species_count / 4);
+        if (completed + 2 < species_count) {
+            col = col + c_disp_settings.settings[completed + 0].color * vals.x;
+            col = col + c_disp_settings.settings[completed + 1].color * vals.y;
+            col = col + c_disp_settings.settings[completed + 2].color * vals.z;
+        }
+        else if (completed + 1 < species_count) {
+            col = col + c_disp_settings.settings[completed + 0].color * vals.x;
+            col = col + c_disp_settings.settings[completed + 1].color * vals.y;
+        }
+        else if (completed < species_count) {
+            col = col + c_disp_settings.settings[completed + 0].color * vals.x;
+        }
+    }
+    textureStore(c_disp_texture, pos, vec4<f32>(col, 1.0));
+}
\ No newline at end of file
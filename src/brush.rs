@@ -0,0 +1,168 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+
+use crate::{MoldConfig, Obstacles};
+
+/// Settings for painting into the trail maps with the mouse. Left-drag
+/// deposits trail for `species`, right-drag erases every species. The mouse
+/// wheel changes `radius`, `[` and `]` change `strength`, the number keys
+/// pick `species` and `O` toggles `walls`.
+#[derive(Clone, Copy, Debug)]
+pub struct Brush {
+    pub species: u32,
+    /// In trail map pixels.
+    pub radius: f32,
+    /// Trail added or removed per second at the centre of the brush.
+    pub strength: f32,
+    /// Fraction of the radius over which the brush fades out at its edge.
+    pub softness: f32,
+    /// Paint [`Obstacles`] instead of trail.
+    pub walls: bool,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Brush {
+            species: 0,
+            radius: 20.,
+            strength: 4.,
+            softness: 0.5,
+            walls: false,
+        }
+    }
+}
+
+/// One drag of the brush from `from` to `to`, in trail map pixels. Texels
+/// within `radius` of the segment get `amount` added, fading out over the
+/// outer `softness` of the radius. A negative `amount` erases, and a negative
+/// `species` paints every species.
+#[repr(C)]
+#[derive(bytemuck::Zeroable, bytemuck::Pod, Clone, Copy, Debug)]
+pub struct BrushStroke {
+    pub from: Vec2,
+    pub to: Vec2,
+    pub radius: f32,
+    pub softness: f32,
+    pub amount: f32,
+    pub species: i32,
+}
+
+/// Maps a cursor position, in logical pixels from the bottom left of a
+/// window of `window_size`, to trail map pixels. The trail map is stretched
+/// over the whole window with its first row at the top.
+pub fn window_to_texture(cursor: Vec2, window_size: Vec2, texture_size: UVec2) -> Vec2 {
+    let uv = cursor / window_size;
+    Vec2::new(uv.x, 1. - uv.y) * texture_size.as_vec2()
+}
+
+/// Brush strokes for the render world, handed over during extraction.
+#[derive(Default)]
+pub(crate) struct PendingBrushStrokes(pub(crate) Vec<BrushStroke>);
+
+pub(crate) fn brush_stroke_collect_system(
+    mut events: EventReader<BrushStroke>,
+    mut pending: ResMut<PendingBrushStrokes>,
+) {
+    pending.0.extend(events.iter().copied());
+}
+
+pub(crate) fn brush_stroke_extract_system(
+    mut pending: ResMut<PendingBrushStrokes>,
+    mut commands: Commands,
+) {
+    commands.insert_resource(std::mem::take(&mut *pending));
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn brush_input_system(
+    brush: Res<Brush>,
+    buttons: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    config: Res<MoldConfig>,
+    time: Res<Time>,
+    mut obstacles: ResMut<Obstacles>,
+    mut last: Local<Option<Vec2>>,
+    mut strokes: EventWriter<BrushStroke>,
+) {
+    let cursor = windows.get_primary().and_then(|window| {
+        let size = Vec2::new(window.width(), window.height());
+        let texture_size = UVec2::new(config.width, config.height);
+        Some(window_to_texture(
+            window.cursor_position()?,
+            size,
+            texture_size,
+        ))
+    });
+    let amount = brush.strength * time.delta_seconds();
+    let (amount, species) = if buttons.pressed(MouseButton::Left) {
+        (amount, brush.species as i32)
+    } else if buttons.pressed(MouseButton::Right) {
+        (-amount, -1)
+    } else {
+        *last = None;
+        return;
+    };
+    if let Some(to) = cursor {
+        let stroke = BrushStroke {
+            from: last.unwrap_or(to),
+            to,
+            radius: brush.radius,
+            softness: brush.softness,
+            amount,
+            species,
+        };
+        if brush.walls {
+            obstacles.paint(&stroke);
+        } else {
+            strokes.send(stroke);
+        }
+    }
+    *last = cursor;
+}
+
+pub(crate) fn brush_settings_system(
+    mut brush: ResMut<Brush>,
+    inp: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    config: Res<MoldConfig>,
+) {
+    for event in wheel.iter() {
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 16.,
+        };
+        brush.radius = (brush.radius * 1.1f32.powf(lines)).clamp(1., 1000.);
+    }
+    if inp.just_pressed(KeyCode::LBracket) {
+        brush.strength /= 1.5;
+    }
+    if inp.just_pressed(KeyCode::RBracket) {
+        brush.strength *= 1.5;
+    }
+    if inp.just_pressed(KeyCode::O) {
+        brush.walls = !brush.walls;
+        info!(
+            "brush paints {}",
+            if brush.walls { "walls" } else { "trail" }
+        );
+    }
+    let digits = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+    for (species, key) in digits.into_iter().enumerate() {
+        if inp.just_pressed(key) && (species as u32) < config.species_count() {
+            brush.species = species as u32;
+            info!("brush species: {}", species);
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use std::{
+    fmt,
+    mem::size_of,
+    path::{Path, PathBuf},
+};
+
+use bevy::math::{UVec2, Vec2, Vec3};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    flow::flow_image_map, food::FoodConfig, spawn::SpawnConfig, Agent, DisplaySettings, Flow,
+    FlowField, GlobalSettings, Kernel, Obstacles, Settings, SpeciesDisplaySettings,
+    SpeciesInteractions, SpeciesSettings,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MoldConfig {
+    pub agent_count: u32,
+    pub width: u32,
+    pub height: u32,
+    pub fixed_delta_time: f32,
+    pub runs_per_frame: usize,
+    pub save_to_disk: Option<PathBuf>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub boundary: Boundary,
+    /// Allow only one agent per cell, like the original Jones model. Moves
+    /// into an occupied cell fail and the agent turns to a random heading.
+    /// When several agents move into the same free cell, the one with the
+    /// highest index wins.
+    #[serde(default)]
+    pub occupancy: bool,
+    #[serde(default)]
+    pub deposition: Deposition,
+    /// Image of walls, see [`Obstacles::from_image`](crate::Obstacles::from_image).
+    #[serde(default)]
+    pub obstacles: Option<PathBuf>,
+    /// Image of flow velocities for `FlowField::Image`, red for x and green
+    /// for y, mid grey for still.
+    #[serde(default)]
+    pub flow_image: Option<PathBuf>,
+    #[serde(default)]
+    pub food: FoodConfig,
+    pub global: GlobalSettings,
+    /// Shape trail diffuses in, the 3×3 box blur if unset.
+    #[serde(default)]
+    pub kernel: Kernel,
+    #[serde(default)]
+    pub flow: Flow,
+    pub species: Vec<SpeciesConfig>,
+    /// How strongly each species follows the trail of every species, one row
+    /// per sensing species with one entry per species sensed. Negative
+    /// weights repel. Without it species follow their own trail with their
+    /// `self_follow` and are repelled by everyone else's with -1.
+    #[serde(default)]
+    pub interactions: Option<Vec<Vec<f32>>>,
+}
+
+/// What happens to agents that move off the map. The numbers are what the
+/// shader sees in `Time::boundary`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ArgEnum)]
+pub enum Boundary {
+    /// Stop at the edge and pick a random heading.
+    #[default]
+    Clamp = 0,
+    /// Come back in on the opposite edge. Sensing and diffusion wrap around
+    /// too, so the output tiles seamlessly.
+    Wrap = 1,
+    /// Stay put and mirror the heading on the edge.
+    Reflect = 2,
+}
+
+/// How agents spread the trail they deposit over the map. Every mode
+/// deposits the same total. The numbers are what the shader sees in
+/// `Time::deposition`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Deposition {
+    /// All of it on the pixel the agent moves onto.
+    #[default]
+    Point = 0,
+    /// Split bilinearly between the four pixels nearest the new position.
+    Bilinear = 1,
+    /// Spread along the whole move, one bilinear splat per pixel travelled,
+    /// so fast agents draw unbroken lines. Moves longer than
+    /// `SWEEP_MAX_SAMPLES` pixels are sampled more sparsely.
+    Swept = 2,
+}
+
+/// Most splats a `Deposition::Swept` move is drawn with.
+pub const SWEEP_MAX_SAMPLES: u32 = 64;
+
+/// Largest storage buffer binding wgpu allows by default. It bounds the
+/// deposit, occupancy, flow image and agent buffers, see
+/// `MoldConfig::storage_buffer_sizes`.
+pub const MAX_STORAGE_BINDING_SIZE: u64 = 128 << 20;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpeciesConfig {
+    pub settings: Settings,
+    pub display: DisplaySettings,
+    #[serde(default)]
+    pub spawn: SpawnConfig,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, ron::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => {
+                write!(f, "failed to read config `{}`: {}", path.display(), e)
+            }
+            ConfigError::Parse(path, e) => {
+                write!(f, "failed to parse config `{}`: {}", path.display(), e)
+            }
+            ConfigError::Invalid(msg) => write!(f, "invalid config: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl MoldConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        let config: MoldConfig =
+            ron::from_str(&text).map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.check(true)
+    }
+
+    /// Everything `validate` checks except the images the config refers to,
+    /// which a snapshot doesn't need once it has been saved.
+    pub(crate) fn validate_settings(&self) -> Result<(), ConfigError> {
+        self.check(false)
+    }
+
+    fn check(&self, files: bool) -> Result<(), ConfigError> {
+        let invalid = |msg: String| Err(ConfigError::Invalid(msg));
+        if self.width == 0 || self.height == 0 {
+            return invalid(format!(
+                "texture size must be non-zero, got {}x{}",
+                self.width, self.height
+            ));
+        }
+        if self.agent_count == 0 {
+            return invalid("`agent_count` must be at least 1".into());
+        }
+        if self.species.is_empty() {
+            return invalid("`species` must list at least one species".into());
+        }
+        if let Some((name, size)) = self
+            .storage_buffer_sizes()
+            .into_iter()
+            .find(|(_, size)| *size > MAX_STORAGE_BINDING_SIZE)
+        {
+            return invalid(format!(
+                "{}x{} with {} species and {} agents needs a {} MiB {} buffer, more than the {} MiB wgpu allows",
+                self.width,
+                self.height,
+                self.species.len(),
+                self.agent_count,
+                (size + (1 << 20) - 1) >> 20,
+                name,
+                MAX_STORAGE_BINDING_SIZE >> 20
+            ));
+        }
+        if self.runs_per_frame == 0 {
+            return invalid("`runs_per_frame` must be at least 1".into());
+        }
+        if self.fixed_delta_time.is_nan() || self.fixed_delta_time <= 0.0 {
+            return invalid(format!(
+                "`fixed_delta_time` must be positive, got {}",
+                self.fixed_delta_time
+            ));
+        }
+        if let Some(path) = self.obstacles.as_ref().filter(|_| files) {
+            if let Err(msg) = Obstacles::from_image(path, self.width, self.height) {
+                return invalid(msg);
+            }
+        }
+        let food = match files {
+            true => self.food.validate(),
+            false => self.food.validate_settings(),
+        };
+        if let Err(msg) = food {
+            return invalid(msg);
+        }
+        if let Err(msg) = self.kernel.validate() {
+            return invalid(msg);
+        }
+        if let Some(path) = self.flow_image.as_deref().filter(|_| files) {
+            let size = UVec2::new(self.width, self.height);
+            if let Err(msg) = flow_image_map(Some(path), size) {
+                return invalid(msg);
+            }
+        }
+        if let Err(msg) = self.flow.validate() {
+            return invalid(msg);
+        }
+        if matches!(self.flow.field, FlowField::Image { .. }) && self.flow_image.is_none() {
+            return invalid("`flow` uses `Image` but no `flow_image` is set".into());
+        }
+        if let Some(interactions) = &self.interactions {
+            let count = self.species.len();
+            if interactions.len() != count || interactions.iter().any(|row| row.len() != count) {
+                return invalid(format!(
+                    "`interactions` must be a {0}x{0} matrix, one row and column per species",
+                    count
+                ));
+            }
+            if interactions
+                .iter()
+                .flatten()
+                .any(|weight| !weight.is_finite())
+            {
+                return invalid("`interactions` must only hold finite weights".into());
+            }
+        }
+        for (i, species) in self.species.iter().enumerate() {
+            if species.settings.sensor_size < 0 {
+                return invalid(format!(
+                    "species {}: `sensor_size` must not be negative, got {}",
+                    i, species.settings.sensor_size
+                ));
+            }
+            if species.settings.trail_weight < 0.0 {
+                return invalid(format!(
+                    "species {}: `trail_weight` must not be negative, got {}",
+                    i, species.settings.trail_weight
+                ));
+            }
+            if species.settings.food_consumption < 0.0 {
+                return invalid(format!(
+                    "species {}: `food_consumption` must not be negative, got {}",
+                    i, species.settings.food_consumption
+                ));
+            }
+            let lifecycle = [
+                ("lifespan", species.settings.lifespan),
+                ("energy_gain", species.settings.energy_gain),
+                ("energy_cost", species.settings.energy_cost),
+                ("split_energy", species.settings.split_energy),
+            ];
+            if let Some((name, value)) = lifecycle.iter().find(|(_, value)| *value < 0.0) {
+                return invalid(format!(
+                    "species {}: `{}` must not be negative, got {}",
+                    i, name, value
+                ));
+            }
+            let spawn = match files {
+                true => species.spawn.validate(),
+                false => species.spawn.validate_settings(),
+            };
+            if let Err(msg) = spawn {
+                return invalid(format!("species {}: {}", i, msg));
+            }
+        }
+        Ok(())
+    }
+
+    /// `u32`s in the occupancy buffer: a flag and then a claim per pixel,
+    /// or a single unused one without `occupancy`.
+    pub(crate) fn occupancy_len(&self) -> u64 {
+        match self.occupancy {
+            true => 2 * self.width as u64 * self.height as u64,
+            false => 1,
+        }
+    }
+
+    /// Bytes in the storage buffers that grow with the map or the agents.
+    /// The food buffer is never larger than the deposit buffer.
+    fn storage_buffer_sizes(&self) -> [(&'static str, u64); 4] {
+        let pixels = self.width as u64 * self.height as u64;
+        let u32_size = size_of::<u32>() as u64;
+        [
+            ("deposit", pixels * self.species.len() as u64 * u32_size),
+            ("occupancy", self.occupancy_len() * u32_size),
+            (
+                "flow image",
+                match self.flow_image {
+                    Some(_) => pixels * size_of::<Vec2>() as u64,
+                    None => 0,
+                },
+            ),
+            ("agent", self.agent_count as u64 * size_of::<Agent>() as u64),
+        ]
+    }
+
+    /// Picks a random seed if none is set and returns it, so the run can be
+    /// reproduced later.
+    pub fn resolve_seed(&mut self) -> u64 {
+        *self.seed.get_or_insert_with(rand::random)
+    }
+
+    /// `seed` folded into the 32 bits the shader RNG works with.
+    pub fn shader_seed(&self) -> u32 {
+        let seed = self.seed.unwrap_or(0);
+        (seed ^ (seed >> 32)) as u32
+    }
+
+    pub fn species_count(&self) -> u32 {
+        self.species.len() as u32
+    }
+
+    /// How strongly species `from` follows the trail of species `to`, see
+    /// `interactions`.
+    pub fn interaction(&self, from: usize, to: usize) -> f32 {
+        match &self.interactions {
+            Some(interactions) => interactions[from][to],
+            None if from == to => self.species[from].settings.self_follow,
+            None => -1.0,
+        }
+    }
+
+    /// Drops species from the end, or adds new ones cycling through the
+    /// existing species' settings with freshly picked colours.
+    /// Rows and columns of `interactions` for new species follow their own
+    /// trail and avoid everyone else's.
+    pub fn set_species_count(&mut self, count: usize) {
+        let existing = self.species.len();
+        if count <= existing || existing == 0 {
+            self.species.truncate(count);
+            if let Some(interactions) = &mut self.interactions {
+                interactions.truncate(count);
+                interactions.iter_mut().for_each(|row| row.truncate(count));
+            }
+            return;
+        }
+        for i in existing..count {
+            let template = &self.species[i % existing];
+            self.species.push(SpeciesConfig {
+                settings: template.settings,
+                display: DisplaySettings {
+                    color: rgb(0.2 + i as f32 / count as f32),
+                    ..template.display
+                },
+                spawn: template.spawn.clone(),
+            });
+        }
+        if let Some(interactions) = &mut self.interactions {
+            for row in interactions.iter_mut() {
+                row.resize(count, -1.0);
+            }
+            for from in existing..count {
+                let mut row = vec![-1.0; count];
+                row[from] = self.species[from].settings.self_follow;
+                interactions.push(row);
+            }
+        }
+    }
+
+    /// Writes the runtime settings resources back into the config, so a
+    /// simulation started from it carries on with them. Settings for a
+    /// different number of species are left out, like the GPU upload does.
+    pub fn apply_settings(
+        &mut self,
+        species: &SpeciesSettings,
+        interactions: &SpeciesInteractions,
+        display: &SpeciesDisplaySettings,
+        global: GlobalSettings,
+        kernel: Kernel,
+        flow: Flow,
+    ) {
+        let count = self.species.len();
+        if species.0.len() == count {
+            for (config, settings) in self.species.iter_mut().zip(&species.0) {
+                config.settings = *settings;
+            }
+        }
+        if display.0.len() == count {
+            for (config, display) in self.species.iter_mut().zip(&display.0) {
+                config.display = *display;
+            }
+        }
+        if interactions.species_count() as usize == count
+            && interactions.weights() != SpeciesInteractions::from_config(self).weights()
+        {
+            self.interactions = Some(
+                interactions
+                    .weights()
+                    .chunks(count)
+                    .map(|row| row.to_vec())
+                    .collect(),
+            );
+        }
+        self.global = global;
+        self.kernel = kernel;
+        self.flow = flow;
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new()).unwrap()
+    }
+}
+
+impl Default for MoldConfig {
+    fn default() -> Self {
+        let species_count = 8;
+        MoldConfig {
+            agent_count: 500_000,
+            width: 1080,
+            height: 1080,
+            fixed_delta_time: 1. / 50.,
+            runs_per_frame: 5,
+            save_to_disk: None,
+            seed: None,
+            boundary: Boundary::default(),
+            deposition: Deposition::default(),
+            occupancy: false,
+            obstacles: None,
+            flow_image: None,
+            food: FoodConfig::default(),
+            global: GlobalSettings {
+                decay_rate: 0.5,
+                diffuse_rate: 4.0,
+            },
+            kernel: Kernel::default(),
+            flow: Flow::default(),
+            species: (0..species_count)
+                .map(|i| SpeciesConfig {
+                    settings: Settings {
+                        trail_weight: 5.0,
+                        self_follow: 4.0,
+                        move_speed: 15.,
+                        turn_speed: 15.,
+                        sensor_angle_degrees: 30.,
+                        sensor_offset: 25.,
+                        sensor_size: 1,
+                        food_weight: 1.0,
+                        food_consumption: 0.0,
+                        lifespan: 0.0,
+                        energy_gain: 0.0,
+                        energy_cost: 0.0,
+                        split_energy: 0.0,
+                        decay_rate: -1.0,
+                        diffuse_rate: -1.0,
+                    },
+                    display: DisplaySettings {
+                        color: rgb(0.2 + i as f32 / species_count as f32),
+                        weight: 1.,
+                    },
+                    spawn: SpawnConfig::default(),
+                })
+                .collect(),
+            interactions: None,
+        }
+    }
+}
+
+fn rgb(hue: f32) -> Vec3 {
+    let adj = (hue % 1.0) * 6.;
+    let v = 1.0 - f32::abs(adj % 2.0 - 1.0);
+    match adj {
+        x if (0.0..1.0).contains(&x) => Vec3::new(1., v, 0.),
+        x if (1.0..2.0).contains(&x) => Vec3::new(v, 1., 0.),
+        x if (2.0..3.0).contains(&x) => Vec3::new(0., 1., v),
+        x if (3.0..4.0).contains(&x) => Vec3::new(0., v, 1.),
+        x if (4.0..5.0).contains(&x) => Vec3::new(v, 0., 1.),
+        x if (5.0..6.0).contains(&x) => Vec3::new(1., 0., v),
+        _ => panic!(),
+    }
+}
@@ -0,0 +1,1092 @@
+//! A pure Rust port of `simulation.wgsl`, for machines without a GPU and as a
+//! reference to check the GPU backend against.
+//!
+//! Every function here mirrors the shader entry point of the same name, down
+//! to the order of floating point operations. Trail maps are rounded to `f16`
+//! on store like the `Rgba16Float` textures they stand in for.
+
+use bevy::{
+    math::{IVec2, IVec3, Vec3, Vec4},
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::Image,
+    },
+};
+use half::f16;
+
+use crate::{
+    apply_agent_change, div_ceil,
+    flow::{flow_image_map, FLOW_CURL_NOISE, FLOW_IMAGE, FLOW_NONE, FLOW_UNIFORM, FLOW_VORTEX},
+    kernel::FULL_KERNEL_STRIDE,
+    population::{agent_spawner_system, PopulationRng},
+    snapshot, Agent, AgentChange, Boundary, BrushStroke, Deposition, DisplaySettings, Flow,
+    FlowUniform, GlobalSettings, Kernel, KernelWeights, LoadSnapshot, MoldConfig, Obstacles,
+    SaveSnapshot, Settings, SpeciesDisplaySettings, SpeciesInteractions, SpeciesSettings,
+    UpdateScreen, MAX_KERNEL_RADIUS, SWEEP_MAX_SAMPLES,
+};
+
+/// The shader spells pi as `3.1415`; matching it keeps headings bit-identical.
+#[allow(clippy::approx_constant)]
+const SHADER_PI: f32 = 3.1415;
+/// Fixed point scale of deposits, `DEPOSIT_SCALE` in the shader.
+const DEPOSIT_SCALE: f32 = 65536.0;
+
+pub struct CpuSimulation {
+    pub settings: Vec<Settings>,
+    pub interactions: SpeciesInteractions,
+    pub display: Vec<DisplaySettings>,
+    pub global: GlobalSettings,
+    pub kernel: Kernel,
+    pub flow: Flow,
+    pub delta: f32,
+    pub obstacles: Obstacles,
+    pub boundary: Boundary,
+    pub deposition: Deposition,
+    pub occupancy: bool,
+    agents: Vec<Agent>,
+    width: u32,
+    height: u32,
+    species_count: u32,
+    /// Ping-pong trail maps, four species packed per texel like the
+    /// `trail_map_a`/`trail_map_b` textures.
+    trail: [Vec<Vec4>; 2],
+    /// Deposits summed during the current step in fixed point, one map per
+    /// species like the `deposits` buffer.
+    painted: Vec<u32>,
+    /// Occupancy flags and then claims per pixel, like the `occupancy`
+    /// buffer.
+    occupancy_cells: Vec<u32>,
+    /// Target, and whether to deposit there, of agents waiting for
+    /// `resolve`.
+    moves: Vec<Option<(Vec2, bool)>>,
+    /// `flow` as of the start of the step.
+    flow_uniform: FlowUniform,
+    flow_image: Vec<Vec2>,
+    food: Vec<f32>,
+    read: usize,
+    step: u32,
+    seed: u32,
+}
+
+impl CpuSimulation {
+    pub fn new(config: &MoldConfig) -> Self {
+        let mut config = config.clone();
+        config.resolve_seed();
+        Self::with_agents(&config, Agent::spawn_initial(&config))
+    }
+
+    pub fn with_agents(config: &MoldConfig, agents: Vec<Agent>) -> Self {
+        let species_count = config.species_count();
+        let pixels = (config.width * config.height) as usize;
+        let layers = div_ceil(species_count, 4) as usize;
+        CpuSimulation {
+            settings: config.species.iter().map(|s| s.settings).collect(),
+            interactions: SpeciesInteractions::from_config(config),
+            display: config.species.iter().map(|s| s.display).collect(),
+            global: config.global,
+            kernel: config.kernel,
+            flow: config.flow,
+            delta: config.fixed_delta_time,
+            obstacles: Obstacles::from_config(config),
+            boundary: config.boundary,
+            deposition: config.deposition,
+            occupancy: config.occupancy,
+            moves: vec![None; agents.len()],
+            agents,
+            width: config.width,
+            height: config.height,
+            species_count,
+            trail: [
+                vec![Vec4::ZERO; pixels * layers],
+                vec![Vec4::ZERO; pixels * layers],
+            ],
+            painted: vec![0; pixels * species_count as usize],
+            occupancy_cells: vec![0; 2 * pixels],
+            flow_uniform: config.flow.uniform(UVec2::new(config.width, config.height)),
+            // Checked by `MoldConfig::validate`.
+            flow_image: flow_image_map(
+                config.flow_image.as_deref(),
+                UVec2::new(config.width, config.height),
+            )
+            .unwrap_or_else(|e| panic!("{}", e)),
+            food: config
+                .food
+                .food_map(UVec2::new(config.width, config.height)),
+            read: 0,
+            step: 0,
+            seed: config.shader_seed(),
+        }
+    }
+
+    pub fn agents(&self) -> &[Agent] {
+        &self.agents
+    }
+
+    pub fn agents_mut(&mut self) -> &mut Vec<Agent> {
+        &mut self.agents
+    }
+
+    /// The trail map the next step reads from, `width * height` texels per
+    /// layer of four species.
+    pub fn trail(&self) -> &[Vec4] {
+        &self.trail[self.read]
+    }
+
+    /// Food left on each pixel, row by row.
+    pub fn food(&self) -> &[f32] {
+        &self.food
+    }
+
+    /// Number of steps run so far.
+    pub fn step_count(&self) -> u32 {
+        self.step
+    }
+
+    /// Runs one step of every pass the GPU backend would, then swaps the
+    /// trail maps.
+    pub fn step(&mut self) {
+        self.flow_uniform = self.flow.uniform(self.dim().as_uvec2());
+        if self.occupancy {
+            self.occupancy_cells.iter_mut().for_each(|v| *v = 0);
+            self.moves.resize(self.agents.len(), None);
+            for id in 0..self.agents.len() {
+                self.occupy(id as u32);
+            }
+        }
+        for id in 0..self.agents.len() {
+            self.update(id as u32);
+        }
+        if self.occupancy {
+            for id in 0..self.agents.len() {
+                self.resolve(id as u32);
+            }
+        }
+
+        let layers = div_ceil(self.species_count, 4) as i32;
+        let mut out = std::mem::take(&mut self.trail[1 - self.read]);
+        let weights = self.kernel.weights();
+        if self.kernel.is_separable() {
+            // Stands in for the `Rgba32Float` texture between the passes.
+            let mut blurred = vec![Vec4::ZERO; out.len()];
+            for z in 0..layers {
+                for y in 0..self.height as i32 {
+                    for x in 0..self.width as i32 {
+                        let coords = IVec2::new(x, y);
+                        blurred[self.layer_index(coords, z)] = self.blur_x(&weights, coords, z);
+                    }
+                }
+            }
+            for z in 0..layers {
+                for y in 0..self.height as i32 {
+                    for x in 0..self.width as i32 {
+                        let coords = IVec2::new(x, y);
+                        let value = self.blur_y(&weights, &blurred, coords, z);
+                        out[self.layer_index(coords, z)] = value.to_array().map(to_f16).into();
+                    }
+                }
+            }
+        } else {
+            for z in 0..layers {
+                for y in 0..self.height as i32 {
+                    for x in 0..self.width as i32 {
+                        let value = self.blur(&weights, IVec2::new(x, y), z);
+                        out[self.layer_index(IVec2::new(x, y), z)] =
+                            value.to_array().map(to_f16).into();
+                    }
+                }
+            }
+        }
+        self.trail[1 - self.read] = out;
+
+        if self.settings.iter().any(|s| s.food_consumption > 0.0) {
+            for y in 0..self.height as i32 {
+                for x in 0..self.width as i32 {
+                    self.eat(IVec2::new(x, y));
+                }
+            }
+        }
+
+        self.painted.iter_mut().for_each(|v| *v = 0);
+
+        if self.settings.iter().any(|s| s.split_energy > 0.0) {
+            self.lifecycle();
+        }
+
+        self.step = self.step.wrapping_add(1);
+        self.read = 1 - self.read;
+    }
+
+    /// Applies `strokes` like the `brush` pass, then swaps the trail maps.
+    pub fn brush(&mut self, strokes: &[BrushStroke]) {
+        let layers = div_ceil(self.species_count, 4) as i32;
+        let mut out = std::mem::take(&mut self.trail[1 - self.read]);
+        for z in 0..layers {
+            for y in 0..self.height as i32 {
+                for x in 0..self.width as i32 {
+                    let coords = IVec2::new(x, y);
+                    let pos = coords.as_vec2() + Vec2::splat(0.5);
+                    let mut value = self.trail_load(coords, z);
+                    for stroke in strokes {
+                        let along = stroke.to - stroke.from;
+                        let t = ((pos - stroke.from).dot(along) / along.dot(along).max(0.0001))
+                            .clamp(0.0, 1.0);
+                        let dist = pos.distance(stroke.from + along * t);
+                        let edge = (stroke.radius * stroke.softness).max(0.0001);
+                        let weight = ((stroke.radius - dist) / edge).clamp(0.0, 1.0);
+
+                        let mask = if stroke.species < 0 {
+                            Vec4::ONE
+                        } else {
+                            Vec4::from([0, 1, 2, 3].map(|i| {
+                                if z * 4 + i == stroke.species {
+                                    1.0
+                                } else {
+                                    0.0
+                                }
+                            }))
+                        };
+                        value =
+                            (value + mask * stroke.amount * weight).clamp(Vec4::ZERO, Vec4::ONE);
+                    }
+                    out[self.layer_index(coords, z)] = value.to_array().map(to_f16).into();
+                }
+            }
+        }
+        self.trail[1 - self.read] = out;
+        self.read = 1 - self.read;
+    }
+
+    /// Colours the current trail map like the `combine` pass, returning
+    /// `Rgba8Unorm` pixels in row-major order.
+    pub fn combine(&self) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity((self.width * self.height * 4) as usize);
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                let col = self.combine_pixel(IVec2::new(x, y));
+                pixels.extend(col.extend(1.0).to_array().map(to_unorm8));
+            }
+        }
+        pixels
+    }
+
+    fn dim(&self) -> IVec2 {
+        IVec2::new(self.width as i32, self.height as i32)
+    }
+
+    fn layer_index(&self, coords: IVec2, layer: i32) -> usize {
+        ((layer * self.height as i32 + coords.y) * self.width as i32 + coords.x) as usize
+    }
+
+    fn trail_load(&self, coords: IVec2, layer: i32) -> Vec4 {
+        self.trail[self.read][self.layer_index(coords, layer)]
+    }
+
+    /// 1.0 for walls, like the `R8Unorm` obstacle texture.
+    fn obstacle_load(&self, coords: IVec2) -> f32 {
+        self.obstacles.is_wall(coords.x as u32, coords.y as u32) as u8 as f32
+    }
+
+    /// The texel read for `coords`, which may lie outside the map.
+    fn boundary_coords(&self, coords: IVec2, dim: IVec2) -> IVec2 {
+        if self.boundary == Boundary::Wrap {
+            // `%` truncates like WGSL's.
+            return (coords % dim + dim) % dim;
+        }
+        coords.clamp(IVec2::ZERO, dim - IVec2::ONE)
+    }
+
+    fn obstacle_at(&self, pos: Vec2) -> f32 {
+        let dim = self.obstacles.size().as_ivec2();
+        self.obstacle_load(pos.as_ivec2().clamp(IVec2::ZERO, dim - IVec2::ONE))
+    }
+
+    /// Callers keep `species` below the species count, like the shader's
+    /// `painted`, which would read past the end of the buffer otherwise.
+    fn painted_load(&self, coords: IVec2, species: i32) -> f32 {
+        self.painted[self.deposit_index(coords.as_vec2(), species)] as f32 / DEPOSIT_SCALE
+    }
+
+    fn deposit_index(&self, pos: Vec2, species: i32) -> usize {
+        let dim = self.dim().as_uvec2();
+        species as usize * (dim.x * dim.y) as usize + cell_index(pos, dim)
+    }
+
+    /// The move from `from` to `to`, the short way around on wrapping maps.
+    fn deposit_path(&self, from: Vec2, to: Vec2) -> Vec2 {
+        let path = to - from;
+        if self.boundary == Boundary::Wrap {
+            let dim = self.dim().as_vec2();
+            return path - dim * (path / dim + Vec2::splat(0.5)).floor();
+        }
+        path
+    }
+
+    fn deposit_samples(&self, from: Vec2, to: Vec2) -> u32 {
+        if self.deposition != Deposition::Swept {
+            return 1;
+        }
+        SWEEP_MAX_SAMPLES.min((self.deposit_path(from, to).length().ceil() as u32).max(1))
+    }
+
+    /// Indices into `painted` and amounts of one splat, like the shader's
+    /// `deposit_splat`.
+    fn deposit_splat(
+        &self,
+        from: Vec2,
+        to: Vec2,
+        sample: u32,
+        samples: u32,
+        amount: u32,
+        species: i32,
+    ) -> [(usize, u32); 4] {
+        let dim = self.dim();
+        let mut pos = to;
+        if sample + 1 < samples {
+            pos = from + self.deposit_path(from, to) * (sample + 1) as f32 / samples as f32;
+            if self.boundary == Boundary::Wrap {
+                pos = wrap_position(pos, dim.as_vec2());
+            }
+        }
+        let total = amount as f32;
+        let share = (total * (sample + 1) as f32 / samples as f32) as u32
+            - (total * sample as f32 / samples as f32) as u32;
+
+        if self.deposition == Deposition::Point {
+            return [
+                (self.deposit_index(pos, species), share),
+                (0, 0),
+                (0, 0),
+                (0, 0),
+            ];
+        }
+
+        let source = pos - Vec2::splat(0.5);
+        let base = source.floor();
+        let f = source - base;
+        let texel = base.as_ivec2();
+        let index = |offset: IVec2| {
+            self.deposit_index(self.boundary_coords(texel + offset, dim).as_vec2(), species)
+        };
+        let part = share as f32;
+        let a0 = (part * ((1.0 - f.x) * (1.0 - f.y))) as u32;
+        let a1 = (part * (f.x * (1.0 - f.y))) as u32;
+        let a2 = (part * ((1.0 - f.x) * f.y)) as u32;
+        let a3 = share - share.min(a0 + a1 + a2);
+        [
+            (index(IVec2::ZERO), a0),
+            (index(IVec2::new(1, 0)), a1),
+            (index(IVec2::new(0, 1)), a2),
+            (index(IVec2::new(1, 1)), a3),
+        ]
+    }
+
+    /// Adds one agent's deposit for its move from `from` to `to`, wrapping
+    /// like the shader's `atomicAdd`.
+    fn deposit(&mut self, from: Vec2, to: Vec2, species: i32) {
+        let weight = self.settings[species as usize].trail_weight;
+        let amount = ((weight * self.delta).max(0.0) * DEPOSIT_SCALE + 0.5) as u32;
+        let samples = self.deposit_samples(from, to);
+        for sample in 0..samples {
+            for (index, amount) in self.deposit_splat(from, to, sample, samples, amount, species) {
+                if amount != 0 {
+                    self.painted[index] = self.painted[index].wrapping_add(amount);
+                }
+            }
+        }
+    }
+
+    fn interaction(&self, from: i32, to: i32, species_count: i32) -> f32 {
+        if to >= species_count {
+            return 0.0;
+        }
+        self.interactions.get(from as u32, to as u32)
+    }
+
+    fn sense(&self, agent: &Agent, sensor_angle_offset: f32) -> f32 {
+        let settings = &self.settings[agent.species as usize];
+
+        let sensor_angle = agent.angle + sensor_angle_offset;
+        let sensor_dir = Vec2::new(sensor_angle.cos(), sensor_angle.sin());
+
+        let sensor_pos = agent.position + sensor_dir * settings.sensor_offset;
+        let sensor_center = sensor_pos.as_ivec2();
+
+        let mut sum = Vec4::ZERO;
+
+        let dim = self.dim();
+        let sensor_size = settings.sensor_size;
+
+        let species_count = self.settings.len() as i32;
+
+        for species in (0..species_count).step_by(4) {
+            let mask = Vec4::from(
+                [0, 1, 2, 3].map(|i| self.interaction(agent.species, species + i, species_count)),
+            );
+            for offset_x in -sensor_size..=sensor_size {
+                for offset_y in -sensor_size..=sensor_size {
+                    let offset = IVec2::new(offset_x, offset_y);
+                    let sample = self.boundary_coords(sensor_center + offset, dim);
+                    sum += mask * self.trail_load(sample, species / 4);
+                }
+            }
+        }
+
+        let mut food = 0.0;
+        if settings.food_weight != 0.0 {
+            for offset_x in -sensor_size..=sensor_size {
+                for offset_y in -sensor_size..=sensor_size {
+                    let offset = IVec2::new(offset_x, offset_y);
+                    let sample = self.boundary_coords(sensor_center + offset, dim);
+                    food += self.food[(sample.y * dim.x + sample.x) as usize];
+                }
+            }
+        }
+
+        sum.x + sum.y + sum.z + sum.w + food * settings.food_weight
+    }
+
+    fn update(&mut self, id: u32) {
+        let dim = self.dim().as_uvec2();
+
+        let agent = self.agents[id as usize];
+        // Dead slot
+        if agent.species < 0 {
+            return;
+        }
+        let settings = self.settings[agent.species as usize];
+        let pos = agent.position;
+
+        let mut random = hash(
+            (pos.y as u32)
+                .wrapping_mul(dim.x)
+                .wrapping_add(pos.x as u32)
+                .wrapping_add(hash(id.wrapping_add(hash(self.seed ^ hash(self.step))))),
+        );
+
+        let sensor_angle_rad = settings.sensor_angle_degrees * (SHADER_PI / 180.0);
+        let weight_forward = self.sense(&agent, 0.0);
+        let weight_left = self.sense(&agent, sensor_angle_rad);
+        let weight_right = self.sense(&agent, -sensor_angle_rad);
+
+        let random_steer_strength = scale_to_range01(random);
+
+        let turn_amount = settings.turn_speed * self.delta;
+
+        let mut new_angle = agent.angle;
+        // Continue in same direction
+        if weight_forward > weight_left && weight_forward > weight_right {
+            new_angle = agent.angle + 0.0;
+        } else if weight_forward < weight_left && weight_forward < weight_right {
+            new_angle = agent.angle + (random_steer_strength - 0.5) * 2.0 * turn_amount;
+        }
+        // Turn right
+        else if weight_right > weight_left {
+            new_angle = agent.angle - random_steer_strength * turn_amount;
+        }
+        // Turn left
+        else if weight_left > weight_right {
+            new_angle = agent.angle + random_steer_strength * turn_amount;
+        }
+
+        let dist = self.delta * settings.move_speed;
+        let dir = Vec2::new(agent.angle.cos(), agent.angle.sin());
+        let drift = self.flow_at(pos, dim) * self.flow_uniform.agent_drift * self.delta;
+        let mut new_pos = agent.position + dist * dir + drift;
+
+        let dimf32 = dim.as_vec2();
+        let mut deposit = false;
+        let mut outside =
+            new_pos.x < 0.0 || new_pos.x >= dimf32.x || new_pos.y < 0.0 || new_pos.y >= dimf32.y;
+        if outside && self.boundary == Boundary::Wrap {
+            new_pos = wrap_position(new_pos, dimf32);
+            outside = false;
+        }
+
+        // Stay put and mirror the heading on the edges that were crossed
+        if outside && self.boundary == Boundary::Reflect {
+            let mut reflected = dir;
+            if new_pos.x < 0.0 || new_pos.x >= dimf32.x {
+                reflected.x = -reflected.x;
+            }
+            if new_pos.y < 0.0 || new_pos.y >= dimf32.y {
+                reflected.y = -reflected.y;
+            }
+            new_pos = pos;
+            new_angle = reflected.y.atan2(reflected.x);
+        }
+        // Clamp position to map boundaries, and pick new random move dir if hit boundary
+        else if outside {
+            random = hash(random);
+            let random_angle = scale_to_range01(random) * 2.0 * SHADER_PI;
+
+            new_pos = new_pos.clamp(Vec2::ZERO, dimf32);
+            if self.obstacle_at(new_pos) > self.obstacle_at(pos) {
+                new_pos = pos;
+            }
+            new_angle = random_angle;
+        }
+        // Turn away from walls instead of entering them. Agents that started
+        // inside a wall may walk out of it.
+        else if self.obstacle_at(new_pos) > self.obstacle_at(pos) {
+            random = hash(random);
+            let random_angle = scale_to_range01(random) * 2.0 * SHADER_PI;
+
+            new_pos = pos;
+            new_angle = random_angle;
+        } else {
+            deposit = true;
+        }
+
+        // In occupancy mode moves into another cell only happen in `resolve`,
+        // once every agent has claimed its cell.
+        let cell = cell_index(new_pos, dim);
+        let mut moved_to = new_pos;
+        if self.occupancy && cell != cell_index(pos, dim) {
+            self.moves[id as usize] = Some((new_pos, deposit));
+            if self.occupancy_cells[cell] == 0 {
+                let claim = &mut self.occupancy_cells[(dim.x * dim.y) as usize + cell];
+                *claim = (*claim).max(id + 1);
+            }
+            moved_to = pos;
+        } else if deposit {
+            self.deposit(pos, new_pos, agent.species);
+        }
+
+        let age = agent.age + self.delta;
+        let energy = agent.energy
+            + (settings.energy_gain * weight_forward.max(0.0) - settings.energy_cost) * self.delta;
+        let agent = &mut self.agents[id as usize];
+        agent.angle = new_angle;
+        agent.position = moved_to;
+        agent.age = age;
+        agent.energy = energy;
+        if (settings.lifespan > 0.0 && age > settings.lifespan)
+            || (settings.energy_cost > 0.0 && energy <= 0.0)
+        {
+            agent.species = -1;
+        }
+    }
+
+    fn occupy(&mut self, id: u32) {
+        let agent = self.agents[id as usize];
+        if agent.is_alive() {
+            let cell = cell_index(agent.position, self.dim().as_uvec2());
+            self.occupancy_cells[cell] = 1;
+        }
+    }
+
+    fn resolve(&mut self, id: u32) {
+        let (position, deposit) = match self.moves[id as usize].take() {
+            Some(pending_move) => pending_move,
+            None => return,
+        };
+        let agent = self.agents[id as usize];
+        if !agent.is_alive() {
+            return;
+        }
+
+        let dim = self.dim().as_uvec2();
+        let cell = cell_index(position, dim);
+        if self.occupancy_cells[(dim.x * dim.y) as usize + cell] == id + 1 {
+            if deposit {
+                self.deposit(agent.position, position, agent.species);
+            }
+            self.agents[id as usize].position = position;
+        } else {
+            let random =
+                hash(hash(id.wrapping_add(hash(self.seed ^ hash(self.step)))).wrapping_add(1));
+            self.agents[id as usize].angle = scale_to_range01(random) * 2.0 * SHADER_PI;
+        }
+    }
+
+    fn can_split(&self, agent: &Agent) -> bool {
+        let split_energy = self.settings[agent.species as usize].split_energy;
+        split_energy > 0.0 && agent.energy >= split_energy
+    }
+
+    fn partner_mask(&self, count: u32) -> u32 {
+        let size = count.max(1).next_power_of_two();
+        1 + hash(self.seed ^ hash(self.step.wrapping_add(1))) % (size - 1).max(1)
+    }
+
+    fn lifecycle(&mut self) {
+        let agent_count = self.agents.len() as u32;
+        let mask = self.partner_mask(agent_count);
+        let next = (0..agent_count)
+            .map(|id| {
+                let mut agent = self.agents[id as usize];
+                let partner_id = id ^ mask;
+                if partner_id < agent_count {
+                    let partner = self.agents[partner_id as usize];
+                    if !agent.is_alive() && partner.is_alive() && self.can_split(&partner) {
+                        agent = partner;
+                        agent.angle = scale_to_range01(hash(
+                            id.wrapping_add(hash(self.seed ^ hash(self.step))),
+                        )) * 2.0
+                            * SHADER_PI;
+                        agent.age = 0.0;
+                        agent.energy = partner.energy * 0.5;
+                    } else if agent.is_alive() && !partner.is_alive() && self.can_split(&agent) {
+                        agent.energy *= 0.5;
+                    }
+                }
+                agent
+            })
+            .collect();
+        self.agents = next;
+    }
+
+    fn eat(&mut self, coords: IVec2) {
+        let cell = (coords.y * self.width as i32 + coords.x) as usize;
+        let mut food = self.food[cell];
+        for species in 0..self.species_count as i32 {
+            if self.painted_load(coords, species) > 0.0 {
+                let consumption = self.settings[species as usize].food_consumption;
+                food = (food - consumption * self.delta).max(0.0);
+            }
+        }
+        self.food[cell] = food;
+    }
+
+    fn fetch_color(&self, coords: IVec2, index: i32) -> Vec4 {
+        let species_count = self.species_count as i32;
+        let mut sum = self.trail_load(coords, index);
+        let species = index * 4;
+        sum += Vec4::new(self.painted_load(coords, species), 0.0, 0.0, 0.0);
+        if species + 1 >= species_count {
+            return sum;
+        }
+        sum += Vec4::new(0.0, self.painted_load(coords, species + 1), 0.0, 0.0);
+        if species + 2 >= species_count {
+            return sum;
+        }
+        sum += Vec4::new(0.0, 0.0, self.painted_load(coords, species + 2), 0.0);
+        if species + 3 >= species_count {
+            return sum;
+        }
+        sum += Vec4::new(0.0, 0.0, 0.0, self.painted_load(coords, species + 3));
+        sum
+    }
+
+    /// The trail the flow carries into `coords` this step, interpolated from
+    /// upstream.
+    fn advected_color(&self, coords: IVec2, index: i32) -> Vec4 {
+        let flow = &self.flow_uniform;
+        if flow.kind == FLOW_NONE || flow.trail_advection == 0.0 {
+            return self.fetch_color(coords, index);
+        }
+        let dim = self.dim();
+        let center = coords.as_vec2() + Vec2::splat(0.5);
+        let source = center
+            - self.flow_at(center, dim.as_uvec2()) * flow.trail_advection * self.delta
+            - Vec2::splat(0.5);
+        let base = source.floor();
+        let f = source - base;
+        let texel = base.as_ivec2();
+        let c00 = self.fetch_color(self.boundary_coords(texel, dim), index);
+        let c10 = self.fetch_color(self.boundary_coords(texel + IVec2::new(1, 0), dim), index);
+        let c01 = self.fetch_color(self.boundary_coords(texel + IVec2::new(0, 1), dim), index);
+        let c11 = self.fetch_color(self.boundary_coords(texel + IVec2::new(1, 1), dim), index);
+        let top = c00 * (1.0 - f.x) + c10 * f.x;
+        let bottom = c01 * (1.0 - f.x) + c11 * f.x;
+        top * (1.0 - f.y) + bottom * f.y
+    }
+
+    /// Random value in [-1, 1] for a corner of the noise lattice.
+    fn lattice(&self, cell: IVec3) -> f32 {
+        scale_to_range01(hash(
+            cell.x as u32 ^ hash(cell.y as u32 ^ hash(cell.z as u32 ^ self.seed)),
+        )) * 2.0
+            - 1.0
+    }
+
+    fn value_noise(&self, p: Vec3) -> f32 {
+        let cell = p.floor();
+        let f = p - cell;
+        let u = f * f * (3.0 - 2.0 * f);
+        let c = cell.as_ivec3();
+        let corner = |x, y, z| self.lattice(c + IVec3::new(x, y, z));
+        let x00 = corner(0, 0, 0) * (1.0 - u.x) + corner(1, 0, 0) * u.x;
+        let x10 = corner(0, 1, 0) * (1.0 - u.x) + corner(1, 1, 0) * u.x;
+        let x01 = corner(0, 0, 1) * (1.0 - u.x) + corner(1, 0, 1) * u.x;
+        let x11 = corner(0, 1, 1) * (1.0 - u.x) + corner(1, 1, 1) * u.x;
+        let y0 = x00 * (1.0 - u.y) + x10 * u.y;
+        let y1 = x01 * (1.0 - u.y) + x11 * u.y;
+        y0 * (1.0 - u.z) + y1 * u.z
+    }
+
+    fn curl_noise(&self, pos: Vec2) -> Vec2 {
+        let flow = &self.flow_uniform;
+        let t = self.step as f32 * self.delta * flow.evolution;
+        let p = (pos / flow.scale).extend(t);
+        let e = 0.01;
+        let dx = self.value_noise(p + Vec3::new(e, 0.0, 0.0))
+            - self.value_noise(p - Vec3::new(e, 0.0, 0.0));
+        let dy = self.value_noise(p + Vec3::new(0.0, e, 0.0))
+            - self.value_noise(p - Vec3::new(0.0, e, 0.0));
+        Vec2::new(dy, -dx) / (2.0 * e)
+    }
+
+    /// Velocity of the flow at `pos`, in pixels per second.
+    fn flow_at(&self, pos: Vec2, dim: UVec2) -> Vec2 {
+        let flow = &self.flow_uniform;
+        match flow.kind {
+            FLOW_UNIFORM => flow.velocity,
+            FLOW_VORTEX => {
+                let d = pos - flow.center;
+                let r2 = d.dot(d) / (flow.scale * flow.scale);
+                Vec2::new(-d.y, d.x) / flow.scale * flow.speed * (0.5 - 0.5 * r2).exp()
+            }
+            FLOW_CURL_NOISE => self.curl_noise(pos) * flow.speed,
+            FLOW_IMAGE => match self.flow_image.get(cell_index(pos, dim)) {
+                Some(velocity) => *velocity * flow.speed,
+                None => Vec2::ZERO,
+            },
+            _ => Vec2::ZERO,
+        }
+    }
+
+    /// The species' own rates, or the global ones where they are negative or
+    /// the species doesn't exist.
+    fn decay_rate(&self, species: i32) -> f32 {
+        match self.settings.get(species as usize) {
+            Some(settings) if settings.decay_rate >= 0.0 => settings.decay_rate,
+            _ => self.global.decay_rate,
+        }
+    }
+
+    fn diffuse_rate(&self, species: i32) -> f32 {
+        match self.settings.get(species as usize) {
+            Some(settings) if settings.diffuse_rate >= 0.0 => settings.diffuse_rate,
+            _ => self.global.diffuse_rate,
+        }
+    }
+
+    fn mix_and_decay(&self, coords: IVec2, species_group_id: i32, mean: Vec4) -> Vec4 {
+        // One rate per species packed into the layer
+        let species = species_group_id * 4;
+        let decay = Vec4::from([0, 1, 2, 3].map(|i| self.decay_rate(species + i)));
+        let diffuse = Vec4::from([0, 1, 2, 3].map(|i| self.diffuse_rate(species + i)));
+
+        let diffuse_weight = (diffuse * self.delta).clamp(Vec4::ZERO, Vec4::ONE);
+
+        let original_color = self.advected_color(coords, species_group_id);
+        let blurred_color = original_color * (1.0 - diffuse_weight) + mean * diffuse_weight;
+
+        (blurred_color - decay * self.delta).max(Vec4::ZERO)
+    }
+
+    fn blur(&self, weights: &KernelWeights, coords: IVec2, species_group_id: i32) -> Vec4 {
+        let dim = self.dim();
+
+        if self.obstacle_load(coords) > 0.0 {
+            return Vec4::ZERO;
+        }
+
+        // Walls take no part in the mean, so trail doesn't spread through them.
+        let radius = weights
+            .radius
+            .min(UVec2::splat(MAX_KERNEL_RADIUS))
+            .as_ivec2();
+        let mut sum = Vec4::ZERO;
+        let mut open_sum = 0.0;
+        for offset_x in -radius.x..=radius.x {
+            for offset_y in -radius.y..=radius.y {
+                let offset = IVec2::new(offset_x, offset_y);
+                let sample = self.boundary_coords(coords + offset, dim);
+                let weight_index = (offset_y + radius.y) as usize * FULL_KERNEL_STRIDE
+                    + (offset_x + radius.x) as usize;
+                let open = (1.0 - self.obstacle_load(sample)) * weights.full[weight_index];
+                sum += self.advected_color(sample, species_group_id) * open;
+                open_sum += open;
+            }
+        }
+
+        self.mix_and_decay(coords, species_group_id, sum / open_sum)
+    }
+
+    /// `sample` gives the value, already multiplied by how open it is, and
+    /// the openness `i` pixels from the one averaged.
+    fn tile_mean(radius: i32, weights: &[f32], sample: impl Fn(i32) -> (Vec4, f32)) -> Vec4 {
+        let mut sum = Vec4::ZERO;
+        let mut open_sum = 0.0;
+        for i in -radius..=radius {
+            let (value, open) = sample(i);
+            let weight = weights[i.unsigned_abs() as usize];
+            sum += value * weight;
+            open_sum += open * weight;
+        }
+        if sample(0).1 < 1.0 {
+            return Vec4::ZERO;
+        }
+        sum / open_sum
+    }
+
+    fn blur_x(&self, weights: &KernelWeights, coords: IVec2, layer: i32) -> Vec4 {
+        let dim = self.dim();
+        let radius = weights.radius.x.min(MAX_KERNEL_RADIUS) as i32;
+        Self::tile_mean(radius, &weights.x, |i| {
+            let sample = self.boundary_coords(coords + IVec2::new(i, 0), dim);
+            let open = 1.0 - self.obstacle_load(sample);
+            (self.advected_color(sample, layer) * open, open)
+        })
+    }
+
+    fn blur_y(&self, weights: &KernelWeights, blurred: &[Vec4], coords: IVec2, layer: i32) -> Vec4 {
+        let dim = self.dim();
+        let radius = weights.radius.y.min(MAX_KERNEL_RADIUS) as i32;
+        let mean = Self::tile_mean(radius, &weights.y, |i| {
+            let sample = self.boundary_coords(coords + IVec2::new(0, i), dim);
+            let open = 1.0 - self.obstacle_load(sample);
+            (blurred[self.layer_index(sample, layer)] * open, open)
+        });
+        if self.obstacle_load(coords) > 0.0 {
+            return Vec4::ZERO;
+        }
+        self.mix_and_decay(coords, layer, mean)
+    }
+
+    fn combine_pixel(&self, pos: IVec2) -> Vec3 {
+        let species_count = self.display.len() as i32;
+        let color = |i: i32| self.display[i as usize].color;
+        let mut col = Vec3::ZERO;
+        for i in 0..species_count / 4 {
+            let vals = self.trail_load(pos, i);
+            col += color(i * 4) * vals.x;
+            col += color(i * 4 + 1) * vals.y;
+            col += color(i * 4 + 2) * vals.z;
+            col += color(i * 4 + 3) * vals.w;
+        }
+        let completed = (species_count / 4) * 4;
+        if species_count % 4 != 0 {
+            let vals = self.trail_load(pos, species_count / 4);
+            if completed + 2 < species_count {
+                col += color(completed) * vals.x;
+                col += color(completed + 1) * vals.y;
+                col += color(completed + 2) * vals.z;
+            } else if completed + 1 < species_count {
+                col += color(completed) * vals.x;
+                col += color(completed + 1) * vals.y;
+            } else if completed < species_count {
+                col += color(completed) * vals.x;
+            }
+        }
+        col
+    }
+}
+
+/// Index of the cell `pos` lies in, positions on the far edges count as the
+/// last cell.
+fn cell_index(pos: Vec2, dim: UVec2) -> usize {
+    let cell = pos.as_uvec2().min(dim - UVec2::ONE);
+    (cell.y * dim.x + cell.x) as usize
+}
+
+fn wrap_position(pos: Vec2, dim: Vec2) -> Vec2 {
+    let wrapped = pos - dim * (pos / dim).floor();
+    // Rounding can land tiny negative positions exactly on the far edge.
+    Vec2::select(wrapped.cmpge(dim), Vec2::ZERO, wrapped)
+}
+
+pub fn hash(state: u32) -> u32 {
+    let mut s = state;
+    s ^= 2747636419;
+    s = s.wrapping_mul(2654435769);
+    s ^= s >> 16;
+    s = s.wrapping_mul(2654435769);
+    s ^= s >> 16;
+    s = s.wrapping_mul(2654435769);
+    s
+}
+
+fn scale_to_range01(state: u32) -> f32 {
+    state as f32 / 4294967295.0
+}
+
+fn to_f16(v: f32) -> f32 {
+    f16::from_f32(v).to_f32()
+}
+
+fn to_unorm8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Runs the simulation on the CPU and shows it in the primary window, in
+/// place of [`MoldPlugin`](crate::MoldPlugin).
+#[derive(Default)]
+pub struct CpuMoldPlugin {
+    pub config: MoldConfig,
+}
+
+struct CpuOutput {
+    image: Handle<Image>,
+    frame: u32,
+}
+
+impl Plugin for CpuMoldPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(save_dir) = &self.config.save_to_disk {
+            std::fs::create_dir_all(save_dir).unwrap();
+        }
+        let mut config = self.config.clone();
+        info!("simulation seed: {}", config.resolve_seed());
+
+        let image = app
+            .world
+            .resource_mut::<Assets<Image>>()
+            .add(Image::new_fill(
+                Extent3d {
+                    width: config.width,
+                    height: config.height,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                &[0, 0, 0, 255],
+                TextureFormat::Rgba8Unorm,
+            ));
+
+        snapshot::add_snapshot_events(app);
+        app.insert_resource(UpdateScreen(true))
+            .insert_resource(config.clone())
+            .insert_resource(SpeciesSettings(
+                config.species.iter().map(|s| s.settings).collect(),
+            ))
+            .insert_resource(SpeciesInteractions::from_config(&config))
+            .insert_resource(SpeciesDisplaySettings(
+                config.species.iter().map(|s| s.display).collect(),
+            ))
+            .insert_resource(config.global)
+            .insert_resource(config.kernel)
+            .insert_resource(config.flow)
+            .insert_resource(Obstacles::from_config(&config))
+            .insert_resource(CpuSimulation::new(&config))
+            .insert_resource(PopulationRng::new(&config))
+            .insert_resource(CpuOutput { image, frame: 0 })
+            .add_event::<AgentChange>()
+            .add_event::<BrushStroke>()
+            .add_startup_system(cpu_setup_system)
+            .add_system(agent_spawner_system)
+            .add_system(cpu_agent_change_system.before("cpu_settings"))
+            .add_system(cpu_brush_system.before("cpu_settings"))
+            .add_system(cpu_settings_system.label("cpu_settings"))
+            .add_system(cpu_step_system.after("cpu_settings"))
+            .add_system(cpu_sprite_size_system)
+            .add_system(cpu_snapshot_system);
+    }
+}
+
+/// Snapshots read the GPU buffers, so the hotkeys only explain why they do
+/// nothing here.
+fn cpu_snapshot_system(mut saves: EventReader<SaveSnapshot>, mut loads: EventReader<LoadSnapshot>) {
+    if saves.iter().count() + loads.iter().count() > 0 {
+        warn!("snapshots only work with the gpu backend");
+    }
+}
+
+fn cpu_setup_system(mut commands: Commands, output: Res<CpuOutput>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands.spawn_bundle(SpriteBundle {
+        texture: output.image.clone(),
+        ..Default::default()
+    });
+}
+
+fn cpu_agent_change_system(
+    mut changes: EventReader<AgentChange>,
+    mut config: ResMut<MoldConfig>,
+    mut sim: ResMut<CpuSimulation>,
+    mut rng: ResMut<PopulationRng>,
+) {
+    for change in changes.iter() {
+        if let Err(e) = apply_agent_change(sim.agents_mut(), change, &config, &mut rng.0) {
+            error!("ignoring {:?}: {}", change, e);
+        }
+        config.agent_count = sim.agents().len() as u32;
+    }
+}
+
+fn cpu_brush_system(mut strokes: EventReader<BrushStroke>, mut sim: ResMut<CpuSimulation>) {
+    let strokes = strokes.iter().copied().collect::<Vec<_>>();
+    if !strokes.is_empty() {
+        sim.brush(&strokes);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cpu_settings_system(
+    mut sim: ResMut<CpuSimulation>,
+    species: Res<SpeciesSettings>,
+    interactions: Res<SpeciesInteractions>,
+    species_display: Res<SpeciesDisplaySettings>,
+    global: Res<GlobalSettings>,
+    kernel: Res<Kernel>,
+    flow: Res<Flow>,
+    obstacles: Res<Obstacles>,
+) {
+    if species.is_changed() && species.0.len() == sim.settings.len() {
+        sim.settings = species.0.clone();
+    }
+    if interactions.is_changed() && interactions.species_count() == sim.interactions.species_count()
+    {
+        sim.interactions = interactions.clone();
+    }
+    if species_display.is_changed() && species_display.0.len() == sim.display.len() {
+        sim.display = species_display.0.clone();
+    }
+    if global.is_changed() {
+        sim.global = *global;
+    }
+    if kernel.is_changed() && kernel.validate().is_ok() {
+        sim.kernel = *kernel;
+    }
+    if flow.is_changed() && flow.validate().is_ok() {
+        sim.flow = *flow;
+    }
+    if obstacles.is_changed() && obstacles.size() == sim.obstacles.size() {
+        sim.obstacles = obstacles.clone();
+    }
+}
+
+fn cpu_step_system(
+    config: Res<MoldConfig>,
+    update_screen: Res<UpdateScreen>,
+    mut sim: ResMut<CpuSimulation>,
+    mut output: ResMut<CpuOutput>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for _ in 0..config.runs_per_frame {
+        sim.step();
+    }
+
+    if !update_screen.0 && config.save_to_disk.is_none() {
+        return;
+    }
+    let pixels = sim.combine();
+    if let Some(save_dir) = &config.save_to_disk {
+        // Like the GPU backend, a frame that can't be written is skipped.
+        let path = save_dir.join(format!("frame_{}.png", output.frame));
+        if let Err(e) = image::save_buffer_with_format(
+            &path,
+            &pixels,
+            config.width,
+            config.height,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        ) {
+            error!("could not write {}: {}", path.display(), e);
+        }
+    }
+    output.frame += 1;
+    if update_screen.0 {
+        images.get_mut(&output.image).unwrap().data = pixels;
+    }
+}
+
+fn cpu_sprite_size_system(windows: Res<Windows>, mut sprites: Query<&mut Sprite>) {
+    if let Some(window) = windows.get_primary() {
+        for mut sprite in sprites.iter_mut() {
+            sprite.custom_size = Some(Vec2::new(window.width(), window.height()));
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::path::Path;
+
+use bevy::math::{UVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+/// A vector field, in pixels per second, that drifts agents and carries trail
+/// along. Changes to the resource are uploaded on the next frame, so a system
+/// can animate it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Flow {
+    #[serde(default)]
+    pub field: FlowField,
+    /// How strongly the flow moves agents, 0 leaves them alone.
+    #[serde(default = "one")]
+    pub agent_drift: f32,
+    /// How strongly the flow moves trail, 0 leaves it alone.
+    #[serde(default = "one")]
+    pub trail_advection: f32,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+impl Default for Flow {
+    fn default() -> Self {
+        Flow {
+            field: FlowField::None,
+            agent_drift: 1.0,
+            trail_advection: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum FlowField {
+    #[default]
+    None,
+    /// The same velocity everywhere.
+    Uniform { velocity: Vec2 },
+    /// Circling `center`, given as a fraction of the map size. Fastest
+    /// `radius` pixels out, where it moves at `speed`, and calm far away.
+    /// Positive speeds turn from +x towards +y.
+    Vortex {
+        center: Vec2,
+        radius: f32,
+        speed: f32,
+    },
+    /// Swirling curl noise with eddies about `scale` pixels across, moving at
+    /// roughly `speed`. The eddies change `evolution` times per second.
+    CurlNoise {
+        scale: f32,
+        speed: f32,
+        #[serde(default)]
+        evolution: f32,
+    },
+    /// `MoldConfig::flow_image`, with full red or green moving at `speed`.
+    Image { speed: f32 },
+}
+
+pub(crate) const FLOW_NONE: u32 = 0;
+pub(crate) const FLOW_UNIFORM: u32 = 1;
+pub(crate) const FLOW_VORTEX: u32 = 2;
+pub(crate) const FLOW_CURL_NOISE: u32 = 3;
+pub(crate) const FLOW_IMAGE: u32 = 4;
+
+/// `Flow` as the shader reads it. `kind` is one of the `FLOW_` constants in
+/// `simulation.wgsl`, `scale` is the vortex radius or the noise scale.
+#[repr(C)]
+#[derive(bytemuck::Zeroable, bytemuck::Pod, Clone, Copy, Debug)]
+pub struct FlowUniform {
+    pub kind: u32,
+    pub speed: f32,
+    pub velocity: Vec2,
+    /// In pixels.
+    pub center: Vec2,
+    pub scale: f32,
+    pub evolution: f32,
+    pub agent_drift: f32,
+    pub trail_advection: f32,
+}
+
+impl Flow {
+    pub fn validate(&self) -> Result<(), String> {
+        let (name, value) = match self.field {
+            FlowField::Vortex { radius, .. } => ("radius", radius),
+            FlowField::CurlNoise { scale, .. } => ("scale", scale),
+            _ => ("", 1.0),
+        };
+        if value.is_nan() || value <= 0.0 {
+            return Err(format!("flow `{}` must be positive, got {}", name, value));
+        }
+        let uniform = self.uniform(UVec2::ONE);
+        let values = [
+            uniform.speed,
+            uniform.velocity.x,
+            uniform.velocity.y,
+            uniform.center.x,
+            uniform.center.y,
+            uniform.evolution,
+            uniform.agent_drift,
+            uniform.trail_advection,
+        ];
+        if values.iter().any(|v| !v.is_finite()) {
+            return Err("flow settings must be finite".into());
+        }
+        Ok(())
+    }
+
+    /// The shader's view of the flow on a map of `size`.
+    pub fn uniform(&self, size: UVec2) -> FlowUniform {
+        let mut uniform = FlowUniform {
+            kind: FLOW_NONE,
+            speed: 0.0,
+            velocity: Vec2::ZERO,
+            center: Vec2::ZERO,
+            scale: 1.0,
+            evolution: 0.0,
+            agent_drift: self.agent_drift,
+            trail_advection: self.trail_advection,
+        };
+        match self.field {
+            FlowField::None => {}
+            FlowField::Uniform { velocity } => {
+                uniform.kind = FLOW_UNIFORM;
+                uniform.velocity = velocity;
+            }
+            FlowField::Vortex {
+                center,
+                radius,
+                speed,
+            } => {
+                uniform.kind = FLOW_VORTEX;
+                uniform.center = center * size.as_vec2();
+                uniform.scale = radius;
+                uniform.speed = speed;
+            }
+            FlowField::CurlNoise {
+                scale,
+                speed,
+                evolution,
+            } => {
+                uniform.kind = FLOW_CURL_NOISE;
+                uniform.scale = scale;
+                uniform.speed = speed;
+                uniform.evolution = evolution;
+            }
+            FlowField::Image { speed } => {
+                uniform.kind = FLOW_IMAGE;
+                uniform.speed = speed;
+            }
+        }
+        uniform
+    }
+}
+
+/// Stretches the image over a map of `size`, red and green from -1 at 0 and 1
+/// through 0 at 128 to 1 at 255, row by row from the top. Without an image
+/// this is a single still pixel, which the shader never reads past.
+pub(crate) fn flow_image_map(path: Option<&Path>, size: UVec2) -> Result<Vec<Vec2>, String> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(vec![Vec2::ZERO]),
+    };
+    let image = image::open(path)
+        .map_err(|e| format!("failed to read flow image `{}`: {}", path.display(), e))?
+        .to_rgb8();
+    Ok((0..size.y)
+        .flat_map(|y| (0..size.x).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let pixel = image.get_pixel(x * image.width() / size.x, y * image.height() / size.y);
+            let [r, g, _] = pixel.0.map(|c| (c as f32 - 128.0) / 127.0);
+            Vec2::new(r, g).max(Vec2::splat(-1.0))
+        })
+        .collect())
+}
@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+
+use bevy::math::{UVec2, Vec2};
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+/// A static attractant field that agents sense on top of the trail maps,
+/// weighted per species by `Settings::food_weight`. Species with a
+/// `food_consumption` eat it up as they pass over it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FoodConfig {
+    /// Image stretched over the map, its brightness is the amount of food.
+    #[serde(default)]
+    pub image: Option<PathBuf>,
+    /// Discs of food added on top of the image.
+    #[serde(default)]
+    pub sources: Vec<FoodSource>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct FoodSource {
+    /// Centre as a fraction of the map size.
+    pub position: Vec2,
+    /// In pixels.
+    pub radius: f32,
+    #[serde(default = "default_amount")]
+    pub amount: f32,
+}
+
+fn default_amount() -> f32 {
+    1.0
+}
+
+impl FoodConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(path) = &self.image {
+            load_image(path)?;
+        }
+        self.validate_settings()
+    }
+
+    /// `validate` without the image.
+    pub(crate) fn validate_settings(&self) -> Result<(), String> {
+        for source in &self.sources {
+            if !(source.position.cmpge(Vec2::ZERO).all() && source.position.cmple(Vec2::ONE).all())
+            {
+                return Err(format!(
+                    "food source `position` must lie within (0, 0) to (1, 1), got {}",
+                    source.position
+                ));
+            }
+            if source.radius <= 0.0 {
+                return Err(format!(
+                    "food source `radius` must be positive, got {}",
+                    source.radius
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The amount of food on each pixel of a map of `size`, row by row from
+    /// the top. The image must have passed `validate`.
+    pub fn food_map(&self, size: UVec2) -> Vec<f32> {
+        let mut food = match &self.image {
+            Some(path) => {
+                let image = load_image(path).unwrap_or_else(|e| panic!("{}", e));
+                (0..size.y)
+                    .flat_map(|y| (0..size.x).map(move |x| (x, y)))
+                    .map(|(x, y)| {
+                        let pixel = image
+                            .get_pixel(x * image.width() / size.x, y * image.height() / size.y);
+                        pixel.0[0] as f32 / 255.
+                    })
+                    .collect()
+            }
+            None => vec![0.0; (size.x * size.y) as usize],
+        };
+        for source in &self.sources {
+            let center = source.position * size.as_vec2();
+            for (i, food) in food.iter_mut().enumerate() {
+                let pixel = UVec2::new(i as u32 % size.x, i as u32 / size.x);
+                if (pixel.as_vec2() + 0.5).distance(center) <= source.radius {
+                    *food += source.amount;
+                }
+            }
+        }
+        food
+    }
+}
+
+fn load_image(path: &Path) -> Result<GrayImage, String> {
+    image::open(path)
+        .map(|image| image.to_luma8())
+        .map_err(|e| format!("failed to read food image `{}`: {}", path.display(), e))
+}
@@ -0,0 +1,52 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bevy::{app::AppExit, prelude::*};
+
+/// Runs a fixed number of simulation steps without drawing to a window, then
+/// exits the app. Frames are still written to `MoldConfig::save_to_disk`.
+#[derive(Clone, Default)]
+pub struct HeadlessRun {
+    pub steps: u64,
+    /// Where to write the image of the last step as PNG.
+    pub final_image: Option<PathBuf>,
+    pub status: HeadlessStatus,
+}
+
+/// Progress of a [`HeadlessRun`], shared between the render world and
+/// whoever started the app so the outcome survives `App::run`.
+#[derive(Clone, Default)]
+pub struct HeadlessStatus {
+    steps_done: Arc<AtomicU64>,
+    outcome: Arc<Mutex<Option<Result<(), String>>>>,
+}
+
+impl HeadlessStatus {
+    pub fn steps_done(&self) -> u64 {
+        self.steps_done.load(Ordering::Relaxed)
+    }
+
+    /// `None` while the run is in progress.
+    pub fn outcome(&self) -> Option<Result<(), String>> {
+        self.outcome.lock().unwrap().clone()
+    }
+
+    pub(crate) fn add_steps(&self, steps: u64) {
+        self.steps_done.fetch_add(steps, Ordering::Relaxed);
+    }
+
+    pub(crate) fn finish(&self, outcome: Result<(), String>) {
+        self.outcome.lock().unwrap().get_or_insert(outcome);
+    }
+}
+
+pub(crate) fn headless_exit_system(run: Res<HeadlessRun>, mut exit: EventWriter<AppExit>) {
+    if run.status.outcome().is_some() {
+        exit.send(AppExit);
+    }
+}
@@ -0,0 +1,181 @@
+use bevy::math::{IVec2, UVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+/// Largest kernel radius the blur supports, in pixels.
+pub const MAX_KERNEL_RADIUS: u32 = 31;
+
+/// Width of the rows `KernelWeights::full` lays the kernel out in.
+pub(crate) const FULL_KERNEL_STRIDE: usize = 64;
+
+/// The shape trail diffuses in. Anything but a box of radius 1 or a turned
+/// ellipse runs as a horizontal and then a vertical pass, so diffusion around
+/// walls is only approximately blocked.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Kernel {
+    /// Mean over a square `2 * radius + 1` pixels wide. A radius of 1 is the
+    /// original single pass 3×3 blur.
+    Box { radius: u32 },
+    /// Gaussian with standard deviation `sigma`, cut off at `radius` or
+    /// three sigmas.
+    Gaussian {
+        sigma: f32,
+        #[serde(default)]
+        radius: Option<u32>,
+    },
+    /// Elliptical Gaussian with standard deviations `sigma` along its axes,
+    /// turned `angle_degrees` from those of the map. A turned ellipse can't
+    /// be split into two passes, so it runs as a single pass over the whole
+    /// kernel and costs the square of its radius per pixel.
+    #[serde(alias = "Anisotropic")]
+    Elliptical {
+        sigma: Vec2,
+        #[serde(default)]
+        angle_degrees: f32,
+    },
+}
+
+impl Default for Kernel {
+    fn default() -> Self {
+        Kernel::Box { radius: 1 }
+    }
+}
+
+/// One side of a separable kernel per axis, as the shader reads it. Index
+/// `i` holds the weight at `i` and `-i` pixels, both axes sum to 1.
+///
+/// A kernel that isn't separable is in `full` instead, the weight at `(x, y)`
+/// pixels from the center at `(y + radius.y) * 64 + x + radius.x`. It isn't
+/// normalized, the blur divides by the weight it sampled anyway.
+#[repr(C)]
+#[derive(bytemuck::Zeroable, bytemuck::Pod, Clone, Copy, Debug)]
+pub struct KernelWeights {
+    pub radius: UVec2,
+    pub x: [f32; MAX_KERNEL_RADIUS as usize + 1],
+    pub y: [f32; MAX_KERNEL_RADIUS as usize + 1],
+    pub full: [f32; FULL_KERNEL_STRIDE * FULL_KERNEL_STRIDE],
+}
+
+impl Kernel {
+    /// Whether the kernel runs as two passes rather than a single pass over
+    /// all of it, which only the 3×3 box and turned ellipses need.
+    pub fn is_separable(&self) -> bool {
+        match *self {
+            Kernel::Box { radius } => radius != 1,
+            Kernel::Gaussian { .. } => true,
+            Kernel::Elliptical { angle_degrees, .. } => angle_degrees == 0.0,
+        }
+    }
+
+    pub fn radius(&self) -> UVec2 {
+        let three_sigmas = |sigma: f32| (3.0 * sigma).ceil().max(1.0) as u32;
+        match *self {
+            Kernel::Box { radius } => UVec2::splat(radius),
+            Kernel::Gaussian { sigma, radius } => {
+                UVec2::splat(radius.unwrap_or_else(|| three_sigmas(sigma)))
+            }
+            Kernel::Elliptical {
+                sigma,
+                angle_degrees,
+            } => {
+                // Half the extent of the three sigma ellipse along each axis.
+                let (sin, cos) = angle_degrees.to_radians().sin_cos();
+                let extent = |a: f32, b: f32| three_sigmas((a * a + b * b).sqrt());
+                UVec2::new(
+                    extent(sigma.x * cos, sigma.y * sin),
+                    extent(sigma.x * sin, sigma.y * cos),
+                )
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let sigmas = match *self {
+            Kernel::Box { .. } => vec![],
+            Kernel::Gaussian { sigma, .. } => vec![sigma],
+            Kernel::Elliptical { sigma, .. } => vec![sigma.x, sigma.y],
+        };
+        if let Some(sigma) = sigmas
+            .into_iter()
+            .find(|sigma| sigma.is_nan() || *sigma <= 0.0)
+        {
+            return Err(format!("kernel `sigma` must be positive, got {}", sigma));
+        }
+        if let Kernel::Elliptical { angle_degrees, .. } = *self {
+            if !angle_degrees.is_finite() {
+                return Err(format!(
+                    "kernel `angle_degrees` must be finite, got {}",
+                    angle_degrees
+                ));
+            }
+        }
+        let radius = self.radius();
+        if radius.min_element() == 0 || radius.max_element() > MAX_KERNEL_RADIUS {
+            return Err(format!(
+                "kernel radius must be between 1 and {}, got {}",
+                MAX_KERNEL_RADIUS,
+                radius.max_element()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn weights(&self) -> KernelWeights {
+        let radius = self.radius();
+        let (sigma_x, sigma_y) = match *self {
+            Kernel::Box { .. } => (None, None),
+            Kernel::Gaussian { sigma, .. } => (Some(sigma), Some(sigma)),
+            Kernel::Elliptical { sigma, .. } => (Some(sigma.x), Some(sigma.y)),
+        };
+        let mut full = [0.0; FULL_KERNEL_STRIDE * FULL_KERNEL_STRIDE];
+        if !self.is_separable() {
+            self.fill_full(radius, &mut full);
+        }
+        KernelWeights {
+            radius,
+            x: one_sided(radius.x, sigma_x),
+            y: one_sided(radius.y, sigma_y),
+            full,
+        }
+    }
+
+    /// Ones for the box, so its mean comes out as the plain 3×3 one.
+    fn fill_full(&self, radius: UVec2, full: &mut [f32]) {
+        let rotated = match *self {
+            Kernel::Elliptical {
+                sigma,
+                angle_degrees,
+            } => Some((sigma, angle_degrees.to_radians().sin_cos())),
+            _ => None,
+        };
+        let radius = radius.min(UVec2::splat(MAX_KERNEL_RADIUS)).as_ivec2();
+        for y in -radius.y..=radius.y {
+            for x in -radius.x..=radius.x {
+                let offset = IVec2::new(x, y) + radius;
+                full[offset.y as usize * FULL_KERNEL_STRIDE + offset.x as usize] = match rotated {
+                    Some((sigma, (sin, cos))) => {
+                        // The offset along the ellipse's own axes, in sigmas.
+                        let u = (x as f32 * cos + y as f32 * sin) / sigma.x;
+                        let v = (y as f32 * cos - x as f32 * sin) / sigma.y;
+                        (-(u * u + v * v) / 2.0).exp()
+                    }
+                    None => 1.0,
+                };
+            }
+        }
+    }
+}
+
+/// Gaussian weights, or a box without a `sigma`, normalized over both sides.
+fn one_sided(radius: u32, sigma: Option<f32>) -> [f32; MAX_KERNEL_RADIUS as usize + 1] {
+    let mut weights = [0.0; MAX_KERNEL_RADIUS as usize + 1];
+    let radius = radius.min(MAX_KERNEL_RADIUS) as usize;
+    for (i, weight) in weights.iter_mut().enumerate().take(radius + 1) {
+        *weight = match sigma {
+            Some(sigma) => (-((i * i) as f32) / (2.0 * sigma * sigma)).exp(),
+            None => 1.0,
+        };
+    }
+    let total = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+    weights.iter_mut().for_each(|weight| *weight /= total);
+    weights
+}
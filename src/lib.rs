@@ -0,0 +1,613 @@
+mod brush;
+mod config;
+pub mod cpu;
+mod flow;
+mod food;
+mod headless;
+mod kernel;
+mod node;
+mod obstacles;
+mod population;
+mod shaders;
+mod snapshot;
+mod spawn;
+
+use std::{path::PathBuf, sync::Arc};
+
+use bevy::{
+    asset::HandleUntyped,
+    core_pipeline,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        render_graph::RenderGraph,
+        render_resource::{Shader, WgpuFeatures},
+        renderer::{RenderDevice, RenderQueue},
+        RenderApp, RenderStage,
+    },
+    window::WindowMode,
+};
+use bytemuck::Zeroable;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+pub use brush::{window_to_texture, Brush, BrushStroke};
+pub use config::{
+    Boundary, ConfigError, Deposition, MoldConfig, SpeciesConfig, MAX_STORAGE_BINDING_SIZE,
+    SWEEP_MAX_SAMPLES,
+};
+pub use cpu::{CpuMoldPlugin, CpuSimulation};
+pub use flow::{Flow, FlowField, FlowUniform};
+pub use food::{FoodConfig, FoodSource};
+pub use headless::{HeadlessRun, HeadlessStatus};
+pub use kernel::{Kernel, KernelWeights, MAX_KERNEL_RADIUS};
+pub use node::MoldNode;
+pub use obstacles::Obstacles;
+pub use population::{apply_agent_change, AgentChange, AgentSpawner};
+pub use shaders::MoldShaders;
+pub use snapshot::{LoadSnapshot, SaveSnapshot, Snapshot, SnapshotError};
+pub use spawn::{Heading, ImageChannel, Region, SpawnConfig, SpawnPattern};
+
+pub const MOLD_NODE: &str = "mold";
+
+/// Features the render device must be created with, via `WgpuSettings`,
+/// before `DefaultPlugins` are added.
+pub const REQUIRED_WGPU_FEATURES: WgpuFeatures =
+    WgpuFeatures::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES.union(WgpuFeatures::CLEAR_COMMANDS);
+
+const SIMULATION_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x6d6f6c645f73696d);
+const DISPLAY_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x6d6f6c645f646973);
+
+/// Runs the simulation described by `config` and draws it to the primary
+/// window, or runs it offline when `headless` is set.
+#[derive(Default)]
+pub struct MoldPlugin {
+    pub config: MoldConfig,
+    /// Load the shaders from `assets/shaders` through the `AssetServer` instead
+    /// of using the copies baked into the library. Together with
+    /// `AssetServerSettings::watch_for_changes` this rebuilds the pipelines
+    /// whenever a shader file is saved.
+    pub hot_reload: bool,
+    /// Run without a window. Leave out `WinitPlugin` and add
+    /// `ScheduleRunnerPlugin` so the app keeps updating until the run is done.
+    pub headless: Option<HeadlessRun>,
+    /// Continue from this snapshot instead of starting from `config`, which
+    /// is then ignored. Unlike sending a `LoadSnapshot` this needs none of
+    /// the images the config refers to.
+    pub snapshot: Option<Arc<Snapshot>>,
+}
+
+#[derive(Clone)]
+pub struct MoldShaderHandles {
+    pub simulation: Handle<Shader>,
+    pub display: Handle<Shader>,
+}
+
+impl Plugin for MoldPlugin {
+    fn build(&self, app: &mut App) {
+        let mut config = match &self.snapshot {
+            Some(snapshot) => snapshot.config.clone(),
+            None => self.config.clone(),
+        };
+        if let Some(save_dir) = &config.save_to_disk {
+            std::fs::create_dir_all(save_dir).unwrap();
+        }
+        info!("simulation seed: {}", config.resolve_seed());
+
+        let handles = if self.hot_reload {
+            let asset_server = app.world.resource::<AssetServer>();
+            MoldShaderHandles {
+                simulation: asset_server.load("shaders/simulation.wgsl"),
+                display: asset_server.load("shaders/display.wgsl"),
+            }
+        } else {
+            let mut shaders = app.world.resource_mut::<Assets<Shader>>();
+            shaders.set_untracked(
+                SIMULATION_SHADER_HANDLE,
+                Shader::from_wgsl(include_str!("../assets/shaders/simulation.wgsl")),
+            );
+            shaders.set_untracked(
+                DISPLAY_SHADER_HANDLE,
+                Shader::from_wgsl(include_str!("../assets/shaders/display.wgsl")),
+            );
+            MoldShaderHandles {
+                simulation: SIMULATION_SHADER_HANDLE.typed(),
+                display: DISPLAY_SHADER_HANDLE.typed(),
+            }
+        };
+
+        let species = SpeciesSettings(config.species.iter().map(|s| s.settings).collect());
+        let interactions = SpeciesInteractions::from_config(&config);
+        let species_display =
+            SpeciesDisplaySettings(config.species.iter().map(|s| s.display).collect());
+
+        let obstacles = match &self.snapshot {
+            Some(snapshot) => snapshot.obstacles(),
+            None => Obstacles::from_config(&config),
+        };
+        let agent_count = population::AgentCount::new(&config);
+
+        app.insert_resource(UpdateScreen(self.headless.is_none()))
+            .insert_resource(config.clone())
+            .insert_resource(obstacles.clone())
+            .insert_resource(handles.clone())
+            .insert_resource(species.clone())
+            .insert_resource(interactions.clone())
+            .insert_resource(species_display.clone())
+            .insert_resource(config.global)
+            .insert_resource(config.kernel)
+            .insert_resource(config.flow);
+        if let Some(run) = &self.headless {
+            app.insert_resource(run.clone())
+                .add_system(headless::headless_exit_system);
+        }
+        snapshot::add_snapshot_events(app);
+        app.init_resource::<snapshot::SnapshotRequests>()
+            .add_system(snapshot::snapshot_request_system);
+        app.add_event::<AgentChange>()
+            .init_resource::<population::PendingAgentChanges>()
+            .insert_resource(agent_count.clone())
+            .add_system(population::agent_spawner_system)
+            .add_system(population::agent_change_collect_system)
+            .add_system(population::agent_count_sync_system);
+        app.add_event::<BrushStroke>()
+            .init_resource::<brush::PendingBrushStrokes>()
+            .add_system(brush::brush_stroke_collect_system);
+
+        let render_app = app.sub_app_mut(RenderApp);
+        let features = render_app.world.resource::<RenderDevice>().features();
+        if !features.contains(REQUIRED_WGPU_FEATURES) {
+            panic!(
+                "MoldPlugin needs the wgpu features {:?}, enable them in `WgpuSettings`",
+                REQUIRED_WGPU_FEATURES - features
+            );
+        }
+
+        render_app
+            .add_system_to_stage(RenderStage::Extract, screen_update_extract_system)
+            .add_system_to_stage(RenderStage::Extract, settings_extract_system)
+            .add_system_to_stage(RenderStage::Extract, snapshot::snapshot_extract_system)
+            .add_system_to_stage(
+                RenderStage::Extract,
+                population::agent_change_extract_system,
+            )
+            .add_system_to_stage(RenderStage::Extract, brush::brush_stroke_extract_system)
+            .add_system_to_stage(RenderStage::Extract, obstacles::obstacle_extract_system)
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                snapshot::snapshot_restore_system.exclusive_system(),
+            )
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                population::agent_change_apply_system.exclusive_system(),
+            )
+            .add_system_to_stage(RenderStage::Prepare, settings_upload_system)
+            .add_system_to_stage(RenderStage::Prepare, snapshot::snapshot_state_system)
+            .add_system_to_stage(RenderStage::Prepare, obstacles::obstacle_upload_system);
+        render_app
+            .insert_resource(snapshot::SnapshotRequests {
+                load: self.snapshot.clone(),
+                ..Default::default()
+            })
+            .init_resource::<population::PendingAgentChanges>()
+            .init_resource::<brush::PendingBrushStrokes>()
+            .insert_resource(population::PopulationRng::new(&config))
+            .insert_resource(agent_count)
+            .insert_resource(config.clone())
+            .insert_resource(obstacles)
+            .insert_resource(handles)
+            .insert_resource(species)
+            .insert_resource(interactions)
+            .insert_resource(species_display)
+            .insert_resource(config.global)
+            .insert_resource(config.kernel)
+            .insert_resource(config.flow);
+        if let Some(run) = &self.headless {
+            render_app.insert_resource(run.clone());
+        }
+        render_app.init_resource::<MoldShaders>();
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(MOLD_NODE, MoldNode::default());
+        graph
+            .add_node_edge(core_pipeline::node::MAIN_PASS_DRIVER, MOLD_NODE)
+            .unwrap();
+    }
+}
+
+/// Keyboard and mouse controls: F11 toggles fullscreen, Space toggles drawing
+/// to the screen, F5 saves a snapshot to `snapshots/` and F9 loads the last one
+/// saved (GPU backend only), `=` and `-` add and remove 10000 agents. Dragging with the left mouse
+/// button paints trail and with the right one erases it, see [`Brush`] for the
+/// brush settings keys.
+pub struct MoldControlsPlugin;
+
+impl Plugin for MoldControlsPlugin {
+    fn build(&self, app: &mut App) {
+        snapshot::add_snapshot_events(app);
+        app.init_resource::<Brush>()
+            .add_system(brush::brush_input_system)
+            .add_system(brush::brush_settings_system)
+            .add_system(fullscreen_system)
+            .add_system(toggle_screen_update_system)
+            .add_system(snapshot_hotkey_system)
+            .add_system(agent_count_hotkey_system);
+    }
+}
+
+fn agent_count_hotkey_system(inp: Res<Input<KeyCode>>, mut changes: EventWriter<AgentChange>) {
+    let count = 10_000;
+    if inp.just_pressed(KeyCode::Equals) {
+        changes.send(AgentChange::Add {
+            species: None,
+            count,
+        });
+    }
+    if inp.just_pressed(KeyCode::Minus) {
+        changes.send(AgentChange::Remove {
+            species: None,
+            count,
+        });
+    }
+}
+
+fn snapshot_hotkey_system(
+    inp: Res<Input<KeyCode>>,
+    mut last: Local<Option<PathBuf>>,
+    mut saves: EventWriter<SaveSnapshot>,
+    mut loads: EventWriter<LoadSnapshot>,
+) {
+    if inp.just_pressed(KeyCode::F5) {
+        let path = snapshot::default_snapshot_path();
+        saves.send(SaveSnapshot(path.clone()));
+        *last = Some(path);
+    }
+    if inp.just_pressed(KeyCode::F9) {
+        match &*last {
+            Some(path) => loads.send(LoadSnapshot(path.clone())),
+            None => info!("no snapshot saved yet, press F5 to save one"),
+        }
+    }
+}
+
+fn fullscreen_system(inp: Res<Input<KeyCode>>, mut windows: ResMut<Windows>) {
+    if inp.just_pressed(KeyCode::F11) {
+        let primary = windows.get_primary_mut().unwrap();
+        primary.set_mode(match primary.mode() {
+            WindowMode::Windowed => WindowMode::Fullscreen,
+            _ => WindowMode::Windowed,
+        });
+    }
+}
+
+/// Whether the simulation is drawn to the primary window. The simulation keeps
+/// running while this is `false`.
+#[derive(Clone, Copy)]
+pub struct UpdateScreen(pub bool);
+
+fn toggle_screen_update_system(mut fs: ResMut<UpdateScreen>, inp: Res<Input<KeyCode>>) {
+    if inp.just_pressed(KeyCode::Space) {
+        fs.0 = !fs.0;
+    }
+}
+
+/// Uniform passed to every simulation pass. The shader RNG hashes `step`
+/// and `seed`, so runs with the same seed and config repeat exactly.
+#[repr(C)]
+#[derive(bytemuck::Zeroable, bytemuck::Pod, Clone, Copy, Debug)]
+pub struct PlainTime {
+    pub step: u32,
+    pub seed: u32,
+    pub delta: f32,
+    /// `MoldConfig::boundary` as a number.
+    pub boundary: u32,
+    /// 1 if `MoldConfig::occupancy` is set.
+    pub occupancy: u32,
+    /// `MoldConfig::deposition` as a number.
+    pub deposition: u32,
+}
+
+fn screen_update_extract_system(us: Res<UpdateScreen>, mut commands: Commands) {
+    commands.insert_resource(*us);
+}
+
+/// Per-species simulation settings, indexed by species. Changes are uploaded
+/// to the GPU on the next frame. The number of species is fixed at startup.
+#[derive(Clone, Debug)]
+pub struct SpeciesSettings(pub Vec<Settings>);
+
+/// How strongly each species follows each species' trail, starting out as
+/// `MoldConfig::interactions`. Changes are uploaded to the GPU on the next
+/// frame.
+#[derive(Clone, Debug)]
+pub struct SpeciesInteractions {
+    species_count: u32,
+    weights: Vec<f32>,
+}
+
+impl SpeciesInteractions {
+    pub fn from_config(config: &MoldConfig) -> Self {
+        let count = config.species.len();
+        SpeciesInteractions {
+            species_count: count as u32,
+            weights: (0..count * count)
+                .map(|i| config.interaction(i / count, i % count))
+                .collect(),
+        }
+    }
+
+    pub fn species_count(&self) -> u32 {
+        self.species_count
+    }
+
+    /// How strongly species `from` follows the trail of species `to`.
+    pub fn get(&self, from: u32, to: u32) -> f32 {
+        self.weights[(from * self.species_count + to) as usize]
+    }
+
+    pub fn set(&mut self, from: u32, to: u32, weight: f32) {
+        self.weights[(from * self.species_count + to) as usize] = weight;
+    }
+
+    /// Row-major, one row per sensing species, as laid out on the GPU.
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+}
+
+/// Per-species colours used when combining the trail maps for display.
+#[derive(Clone, Debug)]
+pub struct SpeciesDisplaySettings(pub Vec<DisplaySettings>);
+
+fn settings_extract_system(
+    species: Res<SpeciesSettings>,
+    interactions: Res<SpeciesInteractions>,
+    species_display: Res<SpeciesDisplaySettings>,
+    global: Res<GlobalSettings>,
+    kernel: Res<Kernel>,
+    flow: Res<Flow>,
+    mut commands: Commands,
+) {
+    if species.is_changed() {
+        commands.insert_resource(species.clone());
+    }
+    if interactions.is_changed() {
+        commands.insert_resource(interactions.clone());
+    }
+    if species_display.is_changed() {
+        commands.insert_resource(species_display.clone());
+    }
+    if global.is_changed() {
+        commands.insert_resource(*global);
+    }
+    if kernel.is_changed() {
+        match kernel.validate() {
+            Ok(()) => commands.insert_resource(*kernel),
+            Err(msg) => error!("{}, ignoring the change", msg),
+        }
+    }
+    if flow.is_changed() {
+        match flow.validate() {
+            Ok(()) => commands.insert_resource(*flow),
+            Err(msg) => error!("{}, ignoring the change", msg),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn settings_upload_system(
+    config: Res<MoldConfig>,
+    shaders: Res<MoldShaders>,
+    render_queue: Res<RenderQueue>,
+    species: Res<SpeciesSettings>,
+    interactions: Res<SpeciesInteractions>,
+    species_display: Res<SpeciesDisplaySettings>,
+    global: Res<GlobalSettings>,
+    kernel: Res<Kernel>,
+    flow: Res<Flow>,
+) {
+    let species_count = config.species.len();
+    if species.is_changed() {
+        if species.0.len() == species_count {
+            render_queue.write_buffer(
+                &shaders.settings_buffer,
+                0,
+                bytemuck::cast_slice(&species.0),
+            );
+        } else {
+            error!(
+                "SpeciesSettings has {} entries but the simulation has {} species, ignoring the change",
+                species.0.len(),
+                species_count
+            );
+        }
+    }
+    if interactions.is_changed() {
+        if interactions.species_count() as usize == species_count {
+            render_queue.write_buffer(
+                &shaders.interactions_buffer,
+                0,
+                bytemuck::cast_slice(interactions.weights()),
+            );
+        } else {
+            error!(
+                "SpeciesInteractions is for {} species but the simulation has {}, ignoring the change",
+                interactions.species_count(),
+                species_count
+            );
+        }
+    }
+    if species_display.is_changed() {
+        if species_display.0.len() == species_count {
+            render_queue.write_buffer(
+                &shaders.combine_settings_buffer,
+                0,
+                bytemuck::cast_slice(&species_display.0),
+            );
+        } else {
+            error!(
+                "SpeciesDisplaySettings has {} entries but the simulation has {} species, ignoring the change",
+                species_display.0.len(),
+                species_count
+            );
+        }
+    }
+    if global.is_changed() {
+        render_queue.write_buffer(
+            &shaders.global_settings_buffer,
+            0,
+            bytemuck::bytes_of(&*global),
+        );
+    }
+    if kernel.is_changed() {
+        render_queue.write_buffer(
+            &shaders.kernel_buffer,
+            0,
+            bytemuck::bytes_of(&kernel.weights()),
+        );
+    }
+    if flow.is_changed() {
+        render_queue.write_buffer(
+            &shaders.flow_buffer,
+            0,
+            bytemuck::bytes_of(&flow.uniform(UVec2::new(config.width, config.height))),
+        );
+    }
+}
+
+/// One agent. A negative `species` marks a dead slot, which agents of a
+/// species with a `split_energy` can be born into.
+#[repr(C)]
+#[derive(bytemuck::Zeroable, bytemuck::Pod, Clone, Copy, Debug)]
+pub struct Agent {
+    pub position: Vec2,
+    pub angle: f32,
+    pub species: i32,
+    /// Seconds since the agent spawned or was born.
+    pub age: f32,
+    pub energy: f32,
+}
+
+#[repr(C)]
+#[derive(bytemuck::Zeroable, bytemuck::Pod, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Settings {
+    /// Trail each agent deposits per second. Agents sharing a pixel add up.
+    pub trail_weight: f32,
+    /// How strongly the species follows its own trail, unless
+    /// `MoldConfig::interactions` is set.
+    pub self_follow: f32,
+    pub move_speed: f32,
+    pub turn_speed: f32,
+    pub sensor_angle_degrees: f32,
+    pub sensor_offset: f32,
+    pub sensor_size: i32,
+    /// How strongly the species follows `MoldConfig::food`, relative to its
+    /// own trail.
+    #[serde(default = "default_food_weight")]
+    pub food_weight: f32,
+    /// Food eaten per second from each pixel the species deposited trail on
+    /// this step, the same however many of its agents are on the pixel.
+    #[serde(default)]
+    pub food_consumption: f32,
+    /// Agents die after living this many seconds, 0 for never.
+    #[serde(default)]
+    pub lifespan: f32,
+    /// Energy gained per second per unit of trail or food sensed ahead.
+    #[serde(default)]
+    pub energy_gain: f32,
+    /// Energy spent per second. Agents die when they run out, unless this
+    /// is 0.
+    #[serde(default)]
+    pub energy_cost: f32,
+    /// Agents with at least this much energy split in two, sharing it, as
+    /// soon as a dead slot is free. 0 for never.
+    #[serde(default)]
+    pub split_energy: f32,
+    /// Overrides `GlobalSettings::decay_rate` for the species' trail unless
+    /// negative.
+    #[serde(default = "inherit_rate")]
+    pub decay_rate: f32,
+    /// Overrides `GlobalSettings::diffuse_rate` for the species' trail unless
+    /// negative.
+    #[serde(default = "inherit_rate")]
+    pub diffuse_rate: f32,
+}
+
+fn default_food_weight() -> f32 {
+    1.0
+}
+
+fn inherit_rate() -> f32 {
+    -1.0
+}
+
+/// Trail settings for every species that doesn't set its own.
+#[repr(C)]
+#[derive(bytemuck::Zeroable, bytemuck::Pod, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GlobalSettings {
+    pub decay_rate: f32,
+    pub diffuse_rate: f32,
+}
+
+#[repr(C)]
+#[derive(bytemuck::Zeroable, bytemuck::Pod, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct DisplaySettings {
+    pub color: Vec3,
+    pub weight: f32,
+}
+
+impl Agent {
+    /// Energy every agent starts out with.
+    pub const INITIAL_ENERGY: f32 = 1.0;
+
+    pub fn new(position: Vec2, angle: f32, species: i32) -> Self {
+        Agent {
+            position,
+            angle,
+            species,
+            age: 0.0,
+            energy: Agent::INITIAL_ENERGY,
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.species >= 0
+    }
+
+    /// Generates the starting agents for `config`, each species placed by its
+    /// own `SpawnConfig`. Species are interleaved in the buffer.
+    pub fn spawn_initial(config: &MoldConfig) -> Vec<Agent> {
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let size = UVec2::new(config.width, config.height);
+        let species_count = config.species_count();
+        let mut agents = vec![Agent::zeroed(); config.agent_count as usize];
+        for (species, species_config) in config.species.iter().enumerate() {
+            let count = div_ceil(
+                config.agent_count.saturating_sub(species as u32),
+                species_count,
+            );
+            let placed = species_config.spawn.spawn(&mut rng, size, count);
+            let slots = agents
+                .iter_mut()
+                .skip(species)
+                .step_by(species_count as usize);
+            for (agent, (position, angle)) in slots.zip(placed) {
+                *agent = Agent::new(position, angle, species as i32);
+            }
+        }
+        agents
+    }
+}
+
+pub(crate) fn div_ceil(val: u32, div: u32) -> u32 {
+    let excess = val % div;
+    if excess > 0 {
+        val / div + 1
+    } else {
+        val / div
+    }
+}
@@ -0,0 +1,517 @@
+use std::{mem::size_of, num::NonZeroU32, path::PathBuf, sync::Mutex};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_graph::{NodeRunError, RenderGraphContext},
+        render_resource::{
+            ComputePassDescriptor, ComputePipeline, Extent3d, ImageCopyBuffer, ImageCopyTexture,
+            ImageDataLayout, LoadOp, MapMode, Operations, Origin3d, PipelineCache,
+            RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, TextureAspect,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        view::ExtractedWindows,
+    },
+    window::WindowId,
+};
+
+use crate::{
+    brush::PendingBrushStrokes,
+    div_ceil,
+    snapshot::{SnapshotReadback, SnapshotRequests},
+    Agent, HeadlessRun, Kernel, MoldConfig, MoldShaders, Obstacles, PlainTime, SpeciesSettings,
+    UpdateScreen,
+};
+
+pub struct MoldNode {
+    inner: Mutex<MoldNodeInner>,
+}
+
+struct MoldNodeInner {
+    /// `MoldShaders::generation` of the resources the state below belongs to.
+    generation: u64,
+    steps: u64,
+    frame: u32,
+    state: ReadState,
+    pipelines: LastGoodPipelines,
+    /// Files to write the combined image to. The copy into `read_buffer` is
+    /// recorded in one frame and read back at the start of the next, once it
+    /// has been submitted.
+    pending_saves: Vec<PathBuf>,
+    pending_snapshot: Option<SnapshotReadback>,
+}
+
+impl Default for MoldNode {
+    fn default() -> Self {
+        MoldNode {
+            inner: Mutex::new(MoldNodeInner {
+                generation: 0,
+                steps: 0,
+                frame: 0,
+                state: ReadState::A,
+                pipelines: LastGoodPipelines::default(),
+                pending_saves: Vec::new(),
+                pending_snapshot: None,
+            }),
+        }
+    }
+}
+
+/// The most recent pipelines that compiled successfully. While a reloaded
+/// shader is queued or fails to compile, the pipeline cache has nothing to
+/// hand out, so the simulation keeps running on these instead.
+#[derive(Default)]
+struct LastGoodPipelines {
+    update: Option<ComputePipeline>,
+    occupy: Option<ComputePipeline>,
+    resolve: Option<ComputePipeline>,
+    blur: Option<ComputePipeline>,
+    blur_x: Option<ComputePipeline>,
+    blur_y: Option<ComputePipeline>,
+    eat: Option<ComputePipeline>,
+    lifecycle: Option<ComputePipeline>,
+    brush: Option<ComputePipeline>,
+    combine: Option<ComputePipeline>,
+    display: Option<RenderPipeline>,
+}
+
+impl LastGoodPipelines {
+    fn refresh(&mut self, shaders: &MoldShaders, cache: &PipelineCache) {
+        let compute = |id| cache.get_compute_pipeline(id).cloned();
+        self.update = compute(shaders.update_pipeline).or_else(|| self.update.take());
+        self.occupy = compute(shaders.occupy_pipeline).or_else(|| self.occupy.take());
+        self.resolve = compute(shaders.resolve_pipeline).or_else(|| self.resolve.take());
+        self.blur = compute(shaders.blur_pipeline).or_else(|| self.blur.take());
+        self.blur_x = compute(shaders.blur_x_pipeline).or_else(|| self.blur_x.take());
+        self.blur_y = compute(shaders.blur_y_pipeline).or_else(|| self.blur_y.take());
+        self.eat = compute(shaders.eat_pipeline).or_else(|| self.eat.take());
+        self.lifecycle = compute(shaders.lifecycle_pipeline).or_else(|| self.lifecycle.take());
+        self.brush = compute(shaders.brush_pipeline).or_else(|| self.brush.take());
+        self.combine = compute(shaders.combine_pipeline).or_else(|| self.combine.take());
+        self.display = cache
+            .get_render_pipeline(shaders.display_pipeline)
+            .cloned()
+            .or_else(|| self.display.take());
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum ReadState {
+    A,
+    B,
+}
+
+impl ReadState {
+    fn flipped(self) -> Self {
+        match self {
+            ReadState::A => ReadState::B,
+            ReadState::B => ReadState::A,
+        }
+    }
+}
+
+impl bevy::render::render_graph::Node for MoldNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let config = world.get_resource::<MoldConfig>().unwrap();
+        let shaders = world.get_resource::<MoldShaders>().unwrap();
+        let render_queue = world.get_resource::<RenderQueue>().unwrap();
+        let pipeline_cache = world.get_resource::<PipelineCache>().unwrap();
+        let this = &mut *self.inner.lock().unwrap();
+        if this.generation != shaders.generation {
+            // The resources were rebuilt from a snapshot, whatever was
+            // recorded for the old ones is gone.
+            this.generation = shaders.generation;
+            this.steps = shaders.start_step;
+            this.state = shaders.start_state;
+            this.pipelines = LastGoodPipelines::default();
+            this.pending_saves.clear();
+            this.pending_snapshot = None;
+        }
+        this.pipelines.refresh(shaders, pipeline_cache);
+        let (
+            update_pipeline,
+            occupy_pipeline,
+            resolve_pipeline,
+            blur_pipeline,
+            blur_x_pipeline,
+            blur_y_pipeline,
+            eat_pipeline,
+            lifecycle_pipeline,
+            brush_pipeline,
+            combine_pipeline,
+            display_pipeline,
+        ) = match &this.pipelines {
+            LastGoodPipelines {
+                update: Some(update),
+                occupy: Some(occupy),
+                resolve: Some(resolve),
+                blur: Some(blur),
+                blur_x: Some(blur_x),
+                blur_y: Some(blur_y),
+                eat: Some(eat),
+                lifecycle: Some(lifecycle),
+                brush: Some(brush),
+                combine: Some(combine),
+                display: Some(display),
+            } => (
+                update, occupy, resolve, blur, blur_x, blur_y, eat, lifecycle, brush, combine,
+                display,
+            ),
+            _ => return Ok(()),
+        };
+        let (tex_width, tex_height) = (config.width, config.height);
+        let species_count = config.species_count();
+        let headless = world.get_resource::<HeadlessRun>();
+        let species_settings = &world.resource::<SpeciesSettings>().0;
+        let eating = species_settings
+            .iter()
+            .any(|settings| settings.food_consumption > 0.0);
+        let splitting = species_settings
+            .iter()
+            .any(|settings| settings.split_energy > 0.0);
+        let separable = world.resource::<Kernel>().is_separable();
+
+        if !this.pending_saves.is_empty() {
+            let saved = save_read_buffer(
+                &render_context.render_device,
+                shaders,
+                config,
+                &this.pending_saves,
+            );
+            this.pending_saves.clear();
+            if let Err(e) = saved {
+                match headless {
+                    Some(run) => run.status.finish(Err(e)),
+                    None => error!("{}", e),
+                }
+            }
+        }
+
+        if let Some(snapshot) = this.pending_snapshot.take() {
+            if let Err(e) = snapshot.finish(&render_context.render_device) {
+                error!("{}", e);
+            }
+        }
+
+        let runs = match headless {
+            Some(run) if run.status.outcome().is_some() => return Ok(()),
+            Some(run) if run.status.steps_done() >= run.steps => {
+                run.status.finish(Ok(()));
+                return Ok(());
+            }
+            Some(run) => u64::min(
+                run.steps - run.status.steps_done(),
+                config.runs_per_frame as u64,
+            ) as usize,
+            None => config.runs_per_frame,
+        };
+
+        let strokes = world
+            .get_resource::<PendingBrushStrokes>()
+            .map_or(&[][..], |strokes| &strokes.0);
+        if !strokes.is_empty() {
+            let [brush_bg_a, brush_bg_b] =
+                shaders.brush_bind_groups(&render_context.render_device, strokes);
+            let mut pass =
+                render_context
+                    .command_encoder
+                    .begin_compute_pass(&ComputePassDescriptor {
+                        label: Some("run-brush"),
+                    });
+            pass.set_pipeline(brush_pipeline);
+            pass.set_bind_group(
+                0,
+                match this.state {
+                    ReadState::A => &brush_bg_a,
+                    ReadState::B => &brush_bg_b,
+                },
+                &[],
+            );
+            pass.dispatch(
+                div_ceil(tex_width, 32),
+                div_ceil(tex_height, 32),
+                div_ceil(species_count, 4),
+            );
+            drop(pass);
+            this.state = this.state.flipped();
+        }
+
+        // Queue writes all land before the encoder runs, so every step gets
+        // its own slot in `time_staging_buffer`, copied in as it starts.
+        let times: Vec<_> = (0..runs as u64)
+            .map(|run| PlainTime {
+                step: (this.steps + run) as u32,
+                seed: config.shader_seed(),
+                delta: config.fixed_delta_time,
+                boundary: config.boundary as u32,
+                occupancy: config.occupancy as u32,
+                deposition: config.deposition as u32,
+            })
+            .collect();
+        render_queue.write_buffer(
+            &shaders.time_staging_buffer,
+            0,
+            bytemuck::cast_slice(&times),
+        );
+
+        for run in 0..runs {
+            let time_size = size_of::<PlainTime>() as u64;
+            render_context.command_encoder.copy_buffer_to_buffer(
+                &shaders.time_staging_buffer,
+                run as u64 * time_size,
+                &shaders.time_buffer,
+                0,
+                time_size,
+            );
+
+            if config.occupancy {
+                render_context
+                    .command_encoder
+                    .clear_buffer(&shaders.occupancy_buffer, 0, None);
+            }
+
+            let mut pass =
+                render_context
+                    .command_encoder
+                    .begin_compute_pass(&ComputePassDescriptor {
+                        label: Some("run-update"),
+                    });
+
+            pass.set_bind_group(1, &shaders.time_bg, &[]);
+
+            let (update_bg, blur_bg, blur_x_bg) = match this.state {
+                ReadState::A => (
+                    &shaders.update_bg_a,
+                    &shaders.blur_bg_a,
+                    &shaders.blur_x_bg_a,
+                ),
+                ReadState::B => (
+                    &shaders.update_bg_b,
+                    &shaders.blur_bg_b,
+                    &shaders.blur_x_bg_b,
+                ),
+            };
+
+            if config.occupancy {
+                pass.set_pipeline(occupy_pipeline);
+                pass.set_bind_group(0, &shaders.occupancy_bg, &[]);
+                pass.dispatch(div_ceil(config.agent_count, 32), 1, 1);
+            }
+
+            pass.set_pipeline(update_pipeline);
+            pass.set_bind_group(0, update_bg, &[]);
+            pass.dispatch(div_ceil(config.agent_count, 32), 1, 1);
+
+            if config.occupancy {
+                pass.set_pipeline(resolve_pipeline);
+                pass.set_bind_group(0, &shaders.occupancy_bg, &[]);
+                pass.dispatch(div_ceil(config.agent_count, 32), 1, 1);
+            }
+
+            if separable {
+                pass.set_pipeline(blur_x_pipeline);
+                pass.set_bind_group(0, blur_x_bg, &[]);
+                pass.dispatch(
+                    div_ceil(tex_width, 256),
+                    tex_height,
+                    div_ceil(species_count, 4),
+                );
+                pass.set_pipeline(blur_y_pipeline);
+                pass.set_bind_group(0, blur_bg, &[]);
+                pass.dispatch(
+                    tex_width,
+                    div_ceil(tex_height, 256),
+                    div_ceil(species_count, 4),
+                );
+            } else {
+                pass.set_pipeline(blur_pipeline);
+                pass.set_bind_group(0, blur_bg, &[]);
+                pass.dispatch(
+                    div_ceil(tex_width, 32),
+                    div_ceil(tex_height, 32),
+                    div_ceil(species_count, 4),
+                );
+            }
+
+            if eating {
+                pass.set_pipeline(eat_pipeline);
+                pass.set_bind_group(0, &shaders.eat_bg, &[]);
+                pass.dispatch(div_ceil(tex_width, 32), div_ceil(tex_height, 32), 1);
+            }
+
+            drop(pass);
+
+            render_context
+                .command_encoder
+                .clear_buffer(&shaders.deposit_buffer, 0, None);
+
+            if splitting {
+                let mut pass =
+                    render_context
+                        .command_encoder
+                        .begin_compute_pass(&ComputePassDescriptor {
+                            label: Some("run-lifecycle"),
+                        });
+                pass.set_pipeline(lifecycle_pipeline);
+                pass.set_bind_group(0, &shaders.lifecycle_bg, &[]);
+                pass.set_bind_group(1, &shaders.time_bg, &[]);
+                pass.dispatch(div_ceil(config.agent_count, 32), 1, 1);
+                drop(pass);
+                render_context.command_encoder.copy_buffer_to_buffer(
+                    &shaders.next_agent_buffer,
+                    0,
+                    &shaders.agent_buffer,
+                    0,
+                    (config.agent_count as usize * size_of::<Agent>()) as u64,
+                );
+            }
+
+            this.steps += 1;
+            this.state = this.state.flipped();
+        }
+        if let Some(run) = headless {
+            run.status.add_steps(runs as u64);
+        }
+
+        let mut pass = render_context
+            .command_encoder
+            .begin_compute_pass(&ComputePassDescriptor {
+                label: Some("run-combine"),
+            });
+
+        pass.set_pipeline(combine_pipeline);
+        pass.set_bind_group(
+            0,
+            match this.state {
+                ReadState::A => &shaders.combine_bg_a,
+                ReadState::B => &shaders.combine_bg_b,
+            },
+            &[],
+        );
+        pass.dispatch(div_ceil(tex_width, 32), div_ceil(tex_height, 32), 1);
+
+        drop(pass);
+        let mut saves = Vec::new();
+        if let Some(save_dir) = &config.save_to_disk {
+            saves.push(save_dir.join(format!("frame_{}.png", this.frame)));
+        }
+        if let Some(HeadlessRun {
+            steps,
+            final_image: Some(path),
+            status,
+        }) = headless
+        {
+            if status.steps_done() == *steps {
+                saves.push(path.clone());
+            }
+        }
+        this.frame += 1;
+
+        if !saves.is_empty() {
+            render_context.command_encoder.copy_texture_to_buffer(
+                ImageCopyTexture {
+                    texture: &shaders.combine_texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                ImageCopyBuffer {
+                    buffer: &shaders.read_buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(shaders.read_bytes_per_row),
+                        rows_per_image: NonZeroU32::new(tex_height),
+                    },
+                },
+                Extent3d {
+                    width: tex_width,
+                    height: tex_height,
+                    depth_or_array_layers: 1,
+                },
+            );
+            this.pending_saves = saves;
+        }
+
+        if let Some(SnapshotRequests {
+            save,
+            state: Some(state),
+            ..
+        }) = world.get_resource::<SnapshotRequests>()
+        {
+            this.pending_snapshot = Some(SnapshotReadback::record(
+                render_context,
+                shaders,
+                state,
+                world.resource::<Obstacles>(),
+                this.steps,
+                this.state as u8,
+                save.clone(),
+            ));
+        }
+
+        // Headless apps have no primary window to draw to.
+        let primary = world
+            .get_resource::<ExtractedWindows>()
+            .and_then(|windows| windows.windows.get(&WindowId::primary()));
+        if let Some(ew) = primary.filter(|_| world.resource::<UpdateScreen>().0) {
+            if let Some(swapchain) = &ew.swap_chain_texture {
+                let mut pass =
+                    render_context
+                        .command_encoder
+                        .begin_render_pass(&RenderPassDescriptor {
+                            label: Some("mold_display"),
+                            color_attachments: &[RenderPassColorAttachment {
+                                view: swapchain,
+                                resolve_target: None,
+                                ops: Operations {
+                                    load: LoadOp::Clear(Color::BLACK.into()),
+                                    store: true,
+                                },
+                            }],
+                            depth_stencil_attachment: None,
+                        });
+                pass.set_pipeline(display_pipeline);
+                pass.set_bind_group(0, &shaders.display_bg, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the combined image copied into `read_buffer` to every path in `paths`.
+fn save_read_buffer(
+    render_device: &RenderDevice,
+    shaders: &MoldShaders,
+    config: &MoldConfig,
+    paths: &[PathBuf],
+) -> Result<(), String> {
+    let slice = shaders.read_buffer.slice(..);
+    render_device.map_buffer(&slice, MapMode::Read);
+    let row = 4 * config.width as usize;
+    let pixels = slice
+        .get_mapped_range()
+        .chunks(shaders.read_bytes_per_row as usize)
+        .flat_map(|padded| &padded[..row])
+        .copied()
+        .collect::<Vec<u8>>();
+    shaders.read_buffer.unmap();
+
+    paths.iter().try_for_each(|path| {
+        image::save_buffer_with_format(
+            path,
+            &pixels,
+            config.width,
+            config.height,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        )
+        .map_err(|e| format!("could not write {}: {}", path.display(), e))
+    })
+}
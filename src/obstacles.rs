@@ -0,0 +1,152 @@
+use std::{num::NonZeroU32, path::Path};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect},
+        renderer::RenderQueue,
+    },
+};
+
+use crate::{BrushStroke, MoldConfig, MoldShaders};
+
+/// Walls agents can't move into and trail doesn't diffuse across, one byte
+/// per trail map pixel, 255 for walls and 0 for open space.
+#[derive(Clone, Debug)]
+pub struct Obstacles {
+    width: u32,
+    height: u32,
+    mask: Vec<u8>,
+}
+
+impl Obstacles {
+    /// No walls anywhere.
+    pub fn new(width: u32, height: u32) -> Self {
+        Obstacles {
+            width,
+            height,
+            mask: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// Loads `config.obstacles`, or no walls if it is not set. The image
+    /// must have passed `MoldConfig::validate`.
+    pub fn from_config(config: &MoldConfig) -> Self {
+        match &config.obstacles {
+            Some(path) => Obstacles::from_image(path, config.width, config.height)
+                .unwrap_or_else(|e| panic!("{}", e)),
+            None => Obstacles::new(config.width, config.height),
+        }
+    }
+
+    /// Stretches the image over the map, pixels brighter than half are walls.
+    pub fn from_image(path: &Path, width: u32, height: u32) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("failed to read obstacle image `{}`: {}", path.display(), e))?
+            .to_luma8();
+        let mut obstacles = Obstacles::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = image.get_pixel(x * image.width() / width, y * image.height() / height);
+                obstacles.set_wall(x, y, pixel.0[0] >= 128);
+            }
+        }
+        Ok(obstacles)
+    }
+
+    pub(crate) fn from_mask(width: u32, height: u32, mask: Vec<u8>) -> Self {
+        assert_eq!(mask.len(), (width * height) as usize);
+        Obstacles {
+            width,
+            height,
+            mask,
+        }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        UVec2::new(self.width, self.height)
+    }
+
+    pub fn mask(&self) -> &[u8] {
+        &self.mask
+    }
+
+    pub fn is_wall(&self, x: u32, y: u32) -> bool {
+        self.mask[(y * self.width + x) as usize] != 0
+    }
+
+    pub fn set_wall(&mut self, x: u32, y: u32, wall: bool) {
+        self.mask[(y * self.width + x) as usize] = if wall { 255 } else { 0 };
+    }
+
+    /// Builds walls under the stroke, or tears them down if its `amount` is
+    /// negative. Walls have hard edges, `softness` is ignored.
+    pub fn paint(&mut self, stroke: &BrushStroke) {
+        let min = (stroke.from.min(stroke.to) - stroke.radius).max(Vec2::ZERO);
+        let max = (stroke.from.max(stroke.to) + stroke.radius).min(self.size().as_vec2());
+        let along = stroke.to - stroke.from;
+        for y in min.y as u32..max.y.ceil() as u32 {
+            for x in min.x as u32..max.x.ceil() as u32 {
+                let pos = Vec2::new(x as f32, y as f32) + 0.5;
+                let t =
+                    ((pos - stroke.from).dot(along) / along.dot(along).max(0.0001)).clamp(0.0, 1.0);
+                if pos.distance(stroke.from + along * t) <= stroke.radius {
+                    self.set_wall(x, y, stroke.amount > 0.);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn obstacle_extract_system(obstacles: Res<Obstacles>, mut commands: Commands) {
+    if obstacles.is_changed() {
+        commands.insert_resource(obstacles.clone());
+    }
+}
+
+pub(crate) fn obstacle_upload_system(
+    config: Res<MoldConfig>,
+    obstacles: Res<Obstacles>,
+    shaders: Res<MoldShaders>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !obstacles.is_changed() {
+        return;
+    }
+    if obstacles.size() == UVec2::new(config.width, config.height) {
+        write_obstacles(&render_queue, &shaders, &obstacles);
+    } else {
+        error!(
+            "Obstacles are {} but the trail maps are {}x{}, ignoring the change",
+            obstacles.size(),
+            config.width,
+            config.height
+        );
+    }
+}
+
+pub(crate) fn write_obstacles(
+    render_queue: &RenderQueue,
+    shaders: &MoldShaders,
+    obstacles: &Obstacles,
+) {
+    render_queue.write_texture(
+        ImageCopyTexture {
+            texture: &shaders.obstacle_texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        obstacles.mask(),
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(obstacles.width),
+            rows_per_image: NonZeroU32::new(obstacles.height),
+        },
+        Extent3d {
+            width: obstacles.width,
+            height: obstacles.height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
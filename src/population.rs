@@ -0,0 +1,229 @@
+use std::{
+    mem::size_of,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use bevy::{
+    prelude::*,
+    render::renderer::{RenderDevice, RenderQueue},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{div_ceil, Agent, MoldConfig, MoldShaders, MAX_STORAGE_BINDING_SIZE};
+
+/// Adds or removes agents while the simulation runs. `species: None` spreads
+/// the change over all species. Removing takes the most recently added
+/// agents first and always leaves at least one; added agents are placed by
+/// their species' `SpawnConfig`.
+#[derive(Clone, Debug)]
+pub enum AgentChange {
+    Add { species: Option<u32>, count: u32 },
+    Remove { species: Option<u32>, count: u32 },
+}
+
+/// Adds `per_second` agents every second until `total` have been added, then
+/// despawns its entity.
+#[derive(Component, Clone, Debug)]
+pub struct AgentSpawner {
+    pub species: Option<u32>,
+    pub per_second: f32,
+    pub total: u32,
+    due: f32,
+}
+
+impl AgentSpawner {
+    pub fn new(species: Option<u32>, per_second: f32, total: u32) -> Self {
+        AgentSpawner {
+            species,
+            per_second,
+            total,
+            due: 0.,
+        }
+    }
+}
+
+/// Applies `change` to `agents`, keeping the state of every agent that stays.
+pub fn apply_agent_change(
+    agents: &mut Vec<Agent>,
+    change: &AgentChange,
+    config: &MoldConfig,
+    rng: &mut impl Rng,
+) -> Result<(), String> {
+    let species_count = config.species_count();
+    let (AgentChange::Add { species, .. } | AgentChange::Remove { species, .. }) = change;
+    if let Some(species) = *species {
+        if species >= species_count {
+            return Err(format!(
+                "no species {}, the simulation has {}",
+                species, species_count
+            ));
+        }
+    }
+
+    match *change {
+        AgentChange::Add { species, count } => {
+            let size = (agents.len() as u64 + count as u64) * size_of::<Agent>() as u64;
+            if size > MAX_STORAGE_BINDING_SIZE {
+                return Err(format!(
+                    "{} more agents need a {} MiB agent buffer, more than the {} MiB wgpu allows",
+                    count,
+                    (size + (1 << 20) - 1) >> 20,
+                    MAX_STORAGE_BINDING_SIZE >> 20
+                ));
+            }
+            // Snapshots restore without the spawn images they refer to.
+            for (s, species_config) in config.species.iter().enumerate() {
+                if species.is_none() || species == Some(s as u32) {
+                    species_config
+                        .spawn
+                        .validate()
+                        .map_err(|e| format!("species {}: {}", s, e))?;
+                }
+            }
+            let size = UVec2::new(config.width, config.height);
+            for (s, species_config) in config.species.iter().enumerate() {
+                let count = match species {
+                    Some(species) if species as usize == s => count,
+                    Some(_) => continue,
+                    None => div_ceil(count.saturating_sub(s as u32), species_count),
+                };
+                let placed = species_config.spawn.spawn(rng, size, count);
+                agents.extend(
+                    placed
+                        .into_iter()
+                        .map(|(position, angle)| Agent::new(position, angle, s as i32)),
+                );
+            }
+        }
+        AgentChange::Remove { species, count } => {
+            let mut left = count.min(agents.len().saturating_sub(1) as u32);
+            let mut removed = vec![false; agents.len()];
+            for (i, agent) in agents.iter().enumerate().rev() {
+                if left == 0 {
+                    break;
+                }
+                if species.is_none() || species == Some(agent.species as u32) {
+                    removed[i] = true;
+                    left -= 1;
+                }
+            }
+            let mut removed = removed.into_iter();
+            agents.retain(|_| !removed.next().unwrap());
+        }
+    }
+    Ok(())
+}
+
+/// Picks the places of agents added at runtime, seeded from the config so
+/// the same changes give the same agents.
+pub(crate) struct PopulationRng(pub(crate) StdRng);
+
+impl PopulationRng {
+    pub(crate) fn new(config: &MoldConfig) -> Self {
+        Self::from_seed(config.seed.unwrap_or(0).wrapping_add(1))
+    }
+
+    pub(crate) fn from_seed(seed: u64) -> Self {
+        PopulationRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+/// The number of agents, shared by both worlds so the main world's
+/// `MoldConfig` follows the changes applied in the render world.
+#[derive(Clone)]
+pub(crate) struct AgentCount(pub(crate) Arc<AtomicU32>);
+
+impl AgentCount {
+    pub(crate) fn new(config: &MoldConfig) -> Self {
+        AgentCount(Arc::new(AtomicU32::new(config.agent_count)))
+    }
+
+    pub(crate) fn set(&self, count: u32) {
+        self.0.store(count, Ordering::Relaxed);
+    }
+}
+
+/// Agent changes for the render world, handed over during extraction.
+#[derive(Default)]
+pub(crate) struct PendingAgentChanges(pub(crate) Vec<AgentChange>);
+
+pub(crate) fn agent_spawner_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut spawners: Query<(Entity, &mut AgentSpawner)>,
+    mut changes: EventWriter<AgentChange>,
+) {
+    for (entity, mut spawner) in spawners.iter_mut() {
+        spawner.due += spawner.per_second * time.delta_seconds();
+        let count = (spawner.due as u32).min(spawner.total);
+        if count > 0 {
+            spawner.due -= count as f32;
+            spawner.total -= count;
+            changes.send(AgentChange::Add {
+                species: spawner.species,
+                count,
+            });
+        }
+        if spawner.total == 0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub(crate) fn agent_change_collect_system(
+    mut events: EventReader<AgentChange>,
+    mut pending: ResMut<PendingAgentChanges>,
+) {
+    pending.0.extend(events.iter().cloned());
+}
+
+/// Copies the agent count of the last changes applied in the render world
+/// into the main world's `MoldConfig`.
+pub(crate) fn agent_count_sync_system(
+    count: Res<AgentCount>,
+    mut seen: Local<u32>,
+    mut config: ResMut<MoldConfig>,
+) {
+    let count = count.0.load(Ordering::Relaxed);
+    if count != *seen {
+        *seen = count;
+        config.agent_count = count;
+    }
+}
+
+pub(crate) fn agent_change_extract_system(
+    mut pending: ResMut<PendingAgentChanges>,
+    mut commands: Commands,
+) {
+    commands.insert_resource(std::mem::take(&mut *pending));
+}
+
+/// Reads the agents back, applies the pending changes and swaps in a new
+/// agent buffer.
+pub(crate) fn agent_change_apply_system(world: &mut World) {
+    let changes = std::mem::take(&mut world.resource_mut::<PendingAgentChanges>().0);
+    if changes.is_empty() {
+        return;
+    }
+
+    let count = world.resource_scope(|world, mut shaders: Mut<MoldShaders>| {
+        world.resource_scope(|world, mut rng: Mut<PopulationRng>| {
+            let render_device = world.resource::<RenderDevice>();
+            let render_queue = world.resource::<RenderQueue>();
+            let config = world.resource::<MoldConfig>();
+            let mut agents = shaders.read_agents(render_device, render_queue, config.agent_count);
+            for change in &changes {
+                if let Err(e) = apply_agent_change(&mut agents, change, config, &mut rng.0) {
+                    error!("ignoring {:?}: {}", change, e);
+                }
+            }
+            shaders.set_agents(render_device, &agents);
+            agents.len() as u32
+        })
+    });
+    world.resource_mut::<MoldConfig>().agent_count = count;
+    world.resource::<AgentCount>().set(count);
+}
@@ -0,0 +1,1534 @@
+use std::{
+    mem::size_of,
+    num::NonZeroU32,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+            BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer, BufferBinding,
+            BufferBindingType, BufferDescriptor, BufferInitDescriptor, BufferSize, BufferUsages,
+            CachedComputePipelineId, CachedRenderPipelineId, ColorTargetState, ColorWrites,
+            ComputePipelineDescriptor, Extent3d, Face, FragmentState, FrontFace, ImageCopyTexture,
+            ImageDataLayout, MapMode, MultisampleState, Origin3d, PipelineCache, PolygonMode,
+            PrimitiveState, PrimitiveTopology, RenderPipelineDescriptor, ShaderStages,
+            StorageTextureAccess, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+            TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+            TextureViewDimension, VertexState,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        texture::BevyDefault,
+    },
+};
+use bytemuck::Zeroable;
+
+use crate::{
+    div_ceil,
+    flow::flow_image_map,
+    kernel::KernelWeights,
+    node::ReadState,
+    obstacles::write_obstacles,
+    population::{AgentCount, PopulationRng},
+    snapshot::SnapshotRequests,
+    Agent, BrushStroke, DisplaySettings, FlowUniform, GlobalSettings, MoldConfig,
+    MoldShaderHandles, Obstacles, PlainTime, Settings, Snapshot, SpeciesInteractions,
+};
+
+/// `wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`, which bevy does not re-export.
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// Rows copied out of a texture have to start on a 256 byte boundary.
+pub(crate) fn padded_bytes_per_row(bytes_per_row: u32) -> u32 {
+    div_ceil(bytes_per_row, COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT
+}
+
+static GENERATION: AtomicU64 = AtomicU64::new(1);
+
+pub struct MoldShaders {
+    pub(crate) update_pipeline: CachedComputePipelineId,
+    pub(crate) update_bg_a: BindGroup,
+    pub(crate) update_bg_b: BindGroup,
+    update_bgl: BindGroupLayout,
+    pub(crate) occupy_pipeline: CachedComputePipelineId,
+    pub(crate) resolve_pipeline: CachedComputePipelineId,
+    pub(crate) occupancy_bg: BindGroup,
+    occupancy_bgl: BindGroupLayout,
+    trail_views: [TextureView; 2],
+    obstacle_view: TextureView,
+    pub(crate) blur_pipeline: CachedComputePipelineId,
+    pub(crate) blur_bg_a: BindGroup,
+    pub(crate) blur_bg_b: BindGroup,
+    /// The two halves of a separable kernel, see `Kernel::is_separable`.
+    /// `blur_y` runs with `blur_bg_a` and `blur_bg_b`.
+    pub(crate) blur_x_pipeline: CachedComputePipelineId,
+    pub(crate) blur_y_pipeline: CachedComputePipelineId,
+    pub(crate) blur_x_bg_a: BindGroup,
+    pub(crate) blur_x_bg_b: BindGroup,
+
+    pub(crate) eat_pipeline: CachedComputePipelineId,
+    pub(crate) eat_bg: BindGroup,
+
+    pub(crate) lifecycle_pipeline: CachedComputePipelineId,
+    pub(crate) lifecycle_bg: BindGroup,
+    lifecycle_bgl: BindGroupLayout,
+
+    pub(crate) brush_pipeline: CachedComputePipelineId,
+    brush_bgl: BindGroupLayout,
+
+    pub(crate) combine_pipeline: CachedComputePipelineId,
+    pub(crate) combine_bg_a: BindGroup,
+    pub(crate) combine_bg_b: BindGroup,
+
+    pub(crate) display_pipeline: CachedRenderPipelineId,
+    pub(crate) display_bg: BindGroup,
+
+    pub(crate) combine_texture: Texture,
+    /// Trail deposited during a step, cleared after every step. See
+    /// `DepositBuffer` in `simulation.wgsl`.
+    pub(crate) deposit_buffer: Buffer,
+    pub(crate) obstacle_texture: Texture,
+    pub(crate) agent_buffer: Buffer,
+    /// Written by the `lifecycle` pass and copied back into `agent_buffer`.
+    pub(crate) next_agent_buffer: Buffer,
+    /// One `Move` per agent, for occupancy mode.
+    move_buffer: Buffer,
+    /// Occupancy flags and then claims, one `u32` each per trail map pixel,
+    /// see `MoldConfig::occupancy_len`.
+    pub(crate) occupancy_buffer: Buffer,
+    pub(crate) food_buffer: Buffer,
+    /// What `flow_image_buffer` was created from, kept for snapshots.
+    pub(crate) flow_image: Vec<Vec2>,
+    /// `trail_map_a` and `trail_map_b`.
+    pub(crate) trail_maps: [Texture; 2],
+
+    pub(crate) settings_buffer: Buffer,
+    pub(crate) interactions_buffer: Buffer,
+    pub(crate) combine_settings_buffer: Buffer,
+    pub(crate) global_settings_buffer: Buffer,
+    pub(crate) kernel_buffer: Buffer,
+    pub(crate) flow_buffer: Buffer,
+
+    pub(crate) time_buffer: Buffer,
+    /// One `PlainTime` per step of a frame, copied into `time_buffer`
+    /// before each step.
+    pub(crate) time_staging_buffer: Buffer,
+    pub(crate) time_bg: BindGroup,
+
+    pub(crate) read_buffer: Buffer,
+    pub(crate) read_bytes_per_row: u32,
+
+    /// Changes whenever the resources are rebuilt, which tells `MoldNode` to
+    /// restart from `start_step` and `start_state`.
+    pub(crate) generation: u64,
+    pub(crate) start_step: u64,
+    pub(crate) start_state: ReadState,
+}
+
+impl FromWorld for MoldShaders {
+    /// Starts from the config, or from the snapshot `MoldPlugin` was given.
+    fn from_world(world: &mut World) -> Self {
+        if let Some(snapshot) = world.resource_mut::<SnapshotRequests>().load.take() {
+            return MoldShaders::from_snapshot(world, &snapshot);
+        }
+        let config = world.resource::<MoldConfig>();
+        let size = UVec2::new(config.width, config.height);
+        let agents = Agent::spawn_initial(config);
+        let food = config.food.food_map(size);
+        // Checked by `MoldConfig::validate`.
+        let flow_image =
+            flow_image_map(config.flow_image.as_deref(), size).unwrap_or_else(|e| panic!("{}", e));
+        MoldShaders::new(world, &agents, &food, flow_image)
+    }
+}
+
+impl MoldShaders {
+    /// Rebuilds everything for the snapshot's config and continues from its
+    /// state. The snapshot's config, obstacles and population seed replace
+    /// those of the render world.
+    pub(crate) fn from_snapshot(world: &mut World, snapshot: &Snapshot) -> Self {
+        world.insert_resource(snapshot.config.clone());
+        world.insert_resource(snapshot.obstacles());
+        world.insert_resource(PopulationRng::from_seed(snapshot.population_seed));
+        world
+            .resource::<AgentCount>()
+            .set(snapshot.config.agent_count);
+        let mut shaders = MoldShaders::new(
+            world,
+            &snapshot.agents,
+            &snapshot.food,
+            snapshot.flow_image.clone(),
+        );
+
+        let config = &snapshot.config;
+        let render_queue = world.resource::<RenderQueue>();
+        for (texture, data) in shaders.trail_maps.iter().zip(&snapshot.trail_maps) {
+            render_queue.write_texture(
+                ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                data,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(8 * config.width),
+                    rows_per_image: NonZeroU32::new(config.height),
+                },
+                Extent3d {
+                    width: config.width,
+                    height: config.height,
+                    depth_or_array_layers: div_ceil(config.species_count(), 4),
+                },
+            );
+        }
+        shaders.start_step = snapshot.step;
+        shaders.start_state = match snapshot.read_map {
+            0 => ReadState::A,
+            _ => ReadState::B,
+        };
+        shaders
+    }
+
+    /// Copies the agents back from the GPU, waiting for everything submitted
+    /// so far.
+    pub(crate) fn read_agents(
+        &self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        count: u32,
+    ) -> Vec<Agent> {
+        let size = (count as usize * size_of::<Agent>()) as u64;
+        let staging = render_device.create_buffer(&BufferDescriptor {
+            label: Some("agent_readback"),
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = render_device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&self.agent_buffer, 0, &staging, 0, size);
+        render_queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..);
+        render_device.map_buffer(&slice, MapMode::Read);
+        let mut agents = vec![Agent::zeroed(); count as usize];
+        bytemuck::cast_slice_mut(&mut agents).copy_from_slice(&slice.get_mapped_range());
+        agents
+    }
+
+    /// Replaces the agent buffer and rebuilds the bind groups using it.
+    pub(crate) fn set_agents(&mut self, render_device: &RenderDevice, agents: &[Agent]) {
+        self.agent_buffer = create_agent_buffer(render_device, agents);
+        self.next_agent_buffer = create_agent_buffer(render_device, agents);
+        self.move_buffer = create_move_buffer(render_device, agents.len());
+        self.occupancy_bg = occupancy_bind_group(
+            render_device,
+            &self.occupancy_bgl,
+            &self.agent_buffer,
+            &self.move_buffer,
+            &self.occupancy_buffer,
+            &self.settings_buffer,
+            &self.deposit_buffer,
+            &self.obstacle_view,
+        );
+        self.lifecycle_bg = lifecycle_bind_group(
+            render_device,
+            &self.lifecycle_bgl,
+            &self.agent_buffer,
+            &self.next_agent_buffer,
+            &self.settings_buffer,
+        );
+        [self.update_bg_a, self.update_bg_b] = update_bind_groups(
+            render_device,
+            &self.update_bgl,
+            &self.agent_buffer,
+            &self.settings_buffer,
+            &self.trail_views,
+            &self.deposit_buffer,
+            &self.obstacle_view,
+            &self.food_buffer,
+            &self.interactions_buffer,
+            &self.occupancy_buffer,
+            &self.move_buffer,
+        );
+    }
+
+    /// Bind groups for a `brush` pass applying `strokes`, reading `trail_map_a`
+    /// and writing `trail_map_b` and the other way around.
+    pub(crate) fn brush_bind_groups(
+        &self,
+        render_device: &RenderDevice,
+        strokes: &[BrushStroke],
+    ) -> [BindGroup; 2] {
+        let stroke_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("brush_strokes"),
+            contents: bytemuck::cast_slice(strokes),
+            usage: BufferUsages::STORAGE,
+        });
+        let bind_group = |label, read: &TextureView, write: &TextureView| {
+            render_device.create_bind_group(&BindGroupDescriptor {
+                label: Some(label),
+                layout: &self.brush_bgl,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &stroke_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(read),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(write),
+                    },
+                ],
+            })
+        };
+        let [a, b] = &self.trail_views;
+        [
+            bind_group("mold_brush_bg_a", a, b),
+            bind_group("mold_brush_bg_b", b, a),
+        ]
+    }
+
+    fn new(world: &mut World, agents: &[Agent], food: &[f32], flow_image: Vec<Vec2>) -> Self {
+        let world = world.cell();
+        let config = world.get_resource::<MoldConfig>().unwrap();
+        let render_device = world.get_resource::<RenderDevice>().unwrap();
+        let mut pipeline_cache = world.get_resource_mut::<PipelineCache>().unwrap();
+        let handles = world.get_resource::<MoldShaderHandles>().unwrap();
+        let (tex_width, tex_height) = (config.width, config.height);
+        let species_count = config.species_count();
+
+        let agent_buffer = create_agent_buffer(&render_device, agents);
+        let next_agent_buffer = create_agent_buffer(&render_device, agents);
+        let move_buffer = create_move_buffer(&render_device, agents.len());
+        let occupancy_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("occupancy"),
+            size: config.occupancy_len() * size_of::<u32>() as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let deposit_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("deposits"),
+            size: (tex_width * tex_height * species_count) as u64 * size_of::<u32>() as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (species, disp): (Vec<_>, Vec<_>) = config
+            .species
+            .iter()
+            .map(|species| (species.settings, species.display))
+            .unzip();
+
+        let settings_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("species_settings"),
+            contents: bytemuck::cast_slice(&species),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let interactions_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("species_interactions"),
+            contents: bytemuck::cast_slice(SpeciesInteractions::from_config(&config).weights()),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let combine_settings_buffer =
+            render_device.create_buffer_with_data(&BufferInitDescriptor {
+                label: Some("combine_species_settings"),
+                contents: bytemuck::cast_slice(&disp),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            });
+        let food_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("food"),
+            contents: bytemuck::cast_slice(food),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        });
+        let global_settings_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("global_settings"),
+            contents: bytemuck::bytes_of(&config.global),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let kernel_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("kernel"),
+            contents: bytemuck::bytes_of(&config.kernel.weights()),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let flow_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("flow"),
+            contents: bytemuck::bytes_of(&config.flow.uniform(UVec2::new(tex_width, tex_height))),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let flow_image_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("flow_image"),
+            contents: bytemuck::cast_slice(&flow_image),
+            usage: BufferUsages::STORAGE,
+        });
+
+        let texture_descriptor = TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width: tex_width,
+                height: tex_height,
+                depth_or_array_layers: div_ceil(species_count, 4),
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba16Float,
+            usage: TextureUsages::STORAGE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST,
+        };
+        let primary_texture_a = render_device.create_texture(&TextureDescriptor {
+            label: Some("trail_map_a"),
+            ..texture_descriptor
+        });
+        let primary_texture_b = render_device.create_texture(&TextureDescriptor {
+            label: Some("trail_map_b"),
+            ..texture_descriptor
+        });
+        let blurred_texture = render_device.create_texture(&TextureDescriptor {
+            label: Some("blurred_x_trail_map"),
+            usage: TextureUsages::STORAGE_BINDING,
+            format: TextureFormat::Rgba32Float,
+            ..texture_descriptor
+        });
+        let obstacle_texture = render_device.create_texture(&TextureDescriptor {
+            label: Some("obstacle_texture"),
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            format: TextureFormat::R8Unorm,
+            size: Extent3d {
+                width: tex_width,
+                height: tex_height,
+                depth_or_array_layers: 1,
+            },
+            ..texture_descriptor
+        });
+        let combine_texture = render_device.create_texture(&TextureDescriptor {
+            label: Some("combine_texture"),
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC,
+            format: TextureFormat::Rgba8Unorm,
+            size: Extent3d {
+                width: tex_width,
+                height: tex_height,
+                depth_or_array_layers: 1,
+            },
+            ..texture_descriptor
+        });
+
+        let texture_view_descriptor = TextureViewDescriptor {
+            label: None,
+            format: Some(TextureFormat::Rgba16Float),
+            dimension: Some(TextureViewDimension::D2Array),
+            aspect: TextureAspect::All,
+            base_mip_level: 0,
+            mip_level_count: None,
+            base_array_layer: 0,
+            array_layer_count: NonZeroU32::new(div_ceil(species_count, 4)),
+        };
+        let primary_view_a = primary_texture_a.create_view(&TextureViewDescriptor {
+            label: Some("primary_view_a"),
+            ..texture_view_descriptor
+        });
+        let primary_view_b = primary_texture_b.create_view(&TextureViewDescriptor {
+            label: Some("primary_view_b"),
+            ..texture_view_descriptor
+        });
+        let blurred_view = blurred_texture.create_view(&TextureViewDescriptor {
+            label: Some("blurred_x_view"),
+            format: Some(TextureFormat::Rgba32Float),
+            ..texture_view_descriptor
+        });
+        let obstacle_view = obstacle_texture.create_view(&TextureViewDescriptor {
+            label: Some("obstacle_view"),
+            format: Some(TextureFormat::R8Unorm),
+            dimension: Some(TextureViewDimension::D2),
+            array_layer_count: NonZeroU32::new(1),
+            ..texture_view_descriptor
+        });
+        let combine_view = combine_texture.create_view(&TextureViewDescriptor {
+            label: Some("combine_view"),
+            format: Some(TextureFormat::Rgba8Unorm),
+            dimension: Some(TextureViewDimension::D2),
+            array_layer_count: NonZeroU32::new(1),
+            ..texture_view_descriptor
+        });
+
+        let time_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("time_buffer"),
+            size: size_of::<PlainTime>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let time_staging_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("time_staging_buffer"),
+            size: (config.runs_per_frame * size_of::<PlainTime>()) as u64,
+            usage: BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let time_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("time_bgl"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<PlainTime>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<FlowUniform>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<Vec2>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let time_bg = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("time_bg"),
+            layout: &time_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &time_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &flow_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &flow_image_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+            ],
+        });
+
+        let update_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("mold_update_bgl"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<Agent>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<Settings>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::ReadOnly,
+                        format: TextureFormat::Rgba16Float,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<u32>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<f32>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<f32>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<u32>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(MOVE_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let trail_views = [primary_view_a.clone(), primary_view_b.clone()];
+        let [update_bg_a, update_bg_b] = update_bind_groups(
+            &render_device,
+            &update_bgl,
+            &agent_buffer,
+            &settings_buffer,
+            &trail_views,
+            &deposit_buffer,
+            &obstacle_view,
+            &food_buffer,
+            &interactions_buffer,
+            &occupancy_buffer,
+            &move_buffer,
+        );
+        let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("mold_update".into()),
+            layout: Some(vec![update_bgl.clone(), time_bgl.clone()]),
+            shader: handles.simulation.clone(),
+            shader_defs: vec![],
+            entry_point: "update".into(),
+        });
+
+        let storage = |binding, read_only, min_size: usize| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(min_size as u64),
+            },
+            count: None,
+        };
+        let occupancy_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("mold_occupancy_bgl"),
+            entries: &[
+                storage(0, false, size_of::<Agent>()),
+                storage(1, false, MOVE_SIZE as usize),
+                storage(2, false, size_of::<u32>()),
+                storage(3, true, size_of::<Settings>()),
+                storage(4, false, size_of::<u32>()),
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let occupancy_bg = occupancy_bind_group(
+            &render_device,
+            &occupancy_bgl,
+            &agent_buffer,
+            &move_buffer,
+            &occupancy_buffer,
+            &settings_buffer,
+            &deposit_buffer,
+            &obstacle_view,
+        );
+        let occupancy_pipeline =
+            |label: &'static str, entry_point: &'static str| ComputePipelineDescriptor {
+                label: Some(label.into()),
+                layout: Some(vec![occupancy_bgl.clone(), time_bgl.clone()]),
+                shader: handles.simulation.clone(),
+                shader_defs: vec![],
+                entry_point: entry_point.into(),
+            };
+        let occupy_pipeline =
+            pipeline_cache.queue_compute_pipeline(occupancy_pipeline("mold_occupy", "occupy"));
+        let resolve_pipeline =
+            pipeline_cache.queue_compute_pipeline(occupancy_pipeline("mold_resolve", "resolve"));
+
+        let blur_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("mold_blur_bgl"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<GlobalSettings>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::ReadOnly,
+                        format: TextureFormat::Rgba16Float,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                storage(2, true, size_of::<u32>()),
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: TextureFormat::Rgba16Float,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage(5, true, size_of::<Settings>()),
+                storage(6, true, size_of::<KernelWeights>()),
+                BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::ReadOnly,
+                        format: TextureFormat::Rgba32Float,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let blur_bg_a = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("mold_blur_bg_a"),
+            layout: &blur_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &global_settings_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&primary_view_a),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &deposit_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(&primary_view_b),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&obstacle_view),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &settings_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &kernel_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::TextureView(&blurred_view),
+                },
+            ],
+        });
+        let blur_bg_b = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("mold_blur_bg_b"),
+            layout: &blur_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &global_settings_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&primary_view_b),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &deposit_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(&primary_view_a),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&obstacle_view),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &settings_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &kernel_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::TextureView(&blurred_view),
+                },
+            ],
+        });
+        let blur_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("mold_blur".into()),
+            layout: Some(vec![blur_bgl.clone(), time_bgl.clone()]),
+            shader: handles.simulation.clone(),
+            shader_defs: vec![],
+            entry_point: "blur".into(),
+        });
+        let blur_y_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("mold_blur_y".into()),
+            layout: Some(vec![blur_bgl, time_bgl.clone()]),
+            shader: handles.simulation.clone(),
+            shader_defs: vec![],
+            entry_point: "blur_y".into(),
+        });
+
+        // Binding numbers match `blur_bgl`, as both share the shader's `b_`
+        // globals.
+        let blur_x_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("mold_blur_x_bgl"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::ReadOnly,
+                        format: TextureFormat::Rgba16Float,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                storage(2, true, size_of::<u32>()),
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage(6, true, size_of::<KernelWeights>()),
+                BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: TextureFormat::Rgba32Float,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let blur_x_bind_group = |label, read: &TextureView| {
+            render_device.create_bind_group(&BindGroupDescriptor {
+                label: Some(label),
+                layout: &blur_x_bgl,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(read),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &deposit_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::TextureView(&obstacle_view),
+                    },
+                    BindGroupEntry {
+                        binding: 6,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &kernel_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    BindGroupEntry {
+                        binding: 8,
+                        resource: BindingResource::TextureView(&blurred_view),
+                    },
+                ],
+            })
+        };
+        let blur_x_bg_a = blur_x_bind_group("mold_blur_x_bg_a", &primary_view_a);
+        let blur_x_bg_b = blur_x_bind_group("mold_blur_x_bg_b", &primary_view_b);
+        let blur_x_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("mold_blur_x".into()),
+            layout: Some(vec![blur_x_bgl, time_bgl.clone()]),
+            shader: handles.simulation.clone(),
+            shader_defs: vec![],
+            entry_point: "blur_x".into(),
+        });
+
+        let eat_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("mold_eat_bgl"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<Settings>() as u64),
+                    },
+                    count: None,
+                },
+                storage(1, true, size_of::<u32>()),
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<f32>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let eat_bg = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("mold_eat_bg"),
+            layout: &eat_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &settings_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &deposit_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &food_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(&obstacle_view),
+                },
+            ],
+        });
+        let eat_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("mold_eat".into()),
+            layout: Some(vec![eat_bgl, time_bgl.clone()]),
+            shader: handles.simulation.clone(),
+            shader_defs: vec![],
+            entry_point: "eat".into(),
+        });
+
+        let lifecycle_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("mold_lifecycle_bgl"),
+            entries: &[
+                storage(0, true, size_of::<Agent>()),
+                storage(1, false, size_of::<Agent>()),
+                storage(2, true, size_of::<Settings>()),
+            ],
+        });
+        let lifecycle_bg = lifecycle_bind_group(
+            &render_device,
+            &lifecycle_bgl,
+            &agent_buffer,
+            &next_agent_buffer,
+            &settings_buffer,
+        );
+        let lifecycle_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("mold_lifecycle".into()),
+            layout: Some(vec![lifecycle_bgl.clone(), time_bgl]),
+            shader: handles.simulation.clone(),
+            shader_defs: vec![],
+            entry_point: "lifecycle".into(),
+        });
+
+        let brush_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("mold_brush_bgl"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<BrushStroke>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::ReadOnly,
+                        format: TextureFormat::Rgba16Float,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: TextureFormat::Rgba16Float,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let brush_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("mold_brush".into()),
+            layout: Some(vec![brush_bgl.clone()]),
+            shader: handles.simulation.clone(),
+            shader_defs: vec![],
+            entry_point: "brush".into(),
+        });
+
+        let combine_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("mold_combine_bgl"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<DisplaySettings>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::ReadOnly,
+                        format: TextureFormat::Rgba16Float,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: TextureFormat::Rgba8Unorm,
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let combine_bg_a = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("mold_combine_bg_a"),
+            layout: &combine_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &combine_settings_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&primary_view_a),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&combine_view),
+                },
+            ],
+        });
+        let combine_bg_b = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("mold_combine_bg_b"),
+            layout: &combine_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &combine_settings_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&primary_view_b),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&combine_view),
+                },
+            ],
+        });
+
+        let combine_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("combine".into()),
+            layout: Some(vec![combine_bgl]),
+            shader: handles.simulation.clone(),
+            shader_defs: vec![],
+            entry_point: "combine".into(),
+        });
+
+        let display_bgl = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("mold_bgl"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::StorageTexture {
+                    access: StorageTextureAccess::ReadOnly,
+                    format: TextureFormat::Rgba8Unorm,
+                    view_dimension: TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        });
+
+        let display_bg = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("mold_display_bg"),
+            layout: &display_bgl,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&combine_view),
+            }],
+        });
+
+        let display_pipeline = pipeline_cache.queue_render_pipeline(RenderPipelineDescriptor {
+            label: Some("mold_display".into()),
+            layout: Some(vec![display_bgl]),
+            vertex: VertexState {
+                shader: handles.display.clone(),
+                shader_defs: vec![],
+                entry_point: "vs_main".into(),
+                buffers: vec![],
+            },
+            fragment: Some(FragmentState {
+                shader: handles.display.clone(),
+                shader_defs: vec![],
+                entry_point: "fs_main".into(),
+                targets: vec![ColorTargetState {
+                    format: TextureFormat::bevy_default(),
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::Src,
+                            dst_factor: BlendFactor::OneMinusSrc,
+                            operation: BlendOperation::Add,
+                        },
+                        alpha: BlendComponent {
+                            src_factor: BlendFactor::One,
+                            dst_factor: BlendFactor::One,
+                            operation: BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: ColorWrites::ALL,
+                }],
+            }),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+                unclipped_depth: false,
+            },
+        });
+
+        let read_bytes_per_row = padded_bytes_per_row(4 * tex_width);
+        let read_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("fetch_buffer"),
+            size: (read_bytes_per_row * tex_height) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let shaders = MoldShaders {
+            update_pipeline,
+            update_bg_a,
+            update_bg_b,
+            update_bgl,
+            occupy_pipeline,
+            resolve_pipeline,
+            occupancy_bg,
+            occupancy_bgl,
+            trail_views,
+            obstacle_view,
+
+            blur_pipeline,
+            blur_bg_a,
+            blur_bg_b,
+            blur_x_pipeline,
+            blur_y_pipeline,
+            blur_x_bg_a,
+            blur_x_bg_b,
+
+            eat_pipeline,
+            eat_bg,
+
+            lifecycle_pipeline,
+            lifecycle_bg,
+            lifecycle_bgl,
+
+            brush_pipeline,
+            brush_bgl,
+
+            combine_pipeline,
+            combine_bg_a,
+            combine_bg_b,
+
+            display_pipeline,
+            display_bg,
+
+            combine_texture,
+            deposit_buffer,
+            obstacle_texture,
+            agent_buffer,
+            next_agent_buffer,
+            move_buffer,
+            occupancy_buffer,
+            food_buffer,
+            flow_image,
+            trail_maps: [primary_texture_a, primary_texture_b],
+
+            settings_buffer,
+            interactions_buffer,
+            combine_settings_buffer,
+            global_settings_buffer,
+            kernel_buffer,
+            flow_buffer,
+
+            read_buffer,
+            read_bytes_per_row,
+            time_buffer,
+            time_staging_buffer,
+            time_bg,
+
+            generation: GENERATION.fetch_add(1, Ordering::Relaxed),
+            start_step: 0,
+            start_state: ReadState::A,
+        };
+        write_obstacles(
+            &world.get_resource::<RenderQueue>().unwrap(),
+            &shaders,
+            &world.get_resource::<Obstacles>().unwrap(),
+        );
+        shaders
+    }
+}
+
+fn create_agent_buffer(render_device: &RenderDevice, agents: &[Agent]) -> Buffer {
+    render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("mold_agents"),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        contents: bytemuck::cast_slice(agents),
+    })
+}
+
+/// Size of a `Move` in the shader.
+const MOVE_SIZE: u64 = 16;
+
+fn create_move_buffer(render_device: &RenderDevice, agent_count: usize) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("mold_moves"),
+        size: agent_count.max(1) as u64 * MOVE_SIZE,
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn occupancy_bind_group(
+    render_device: &RenderDevice,
+    layout: &BindGroupLayout,
+    agent_buffer: &Buffer,
+    move_buffer: &Buffer,
+    occupancy_buffer: &Buffer,
+    settings_buffer: &Buffer,
+    deposit_buffer: &Buffer,
+    obstacle_view: &TextureView,
+) -> BindGroup {
+    let buffer = |binding, buffer| BindGroupEntry {
+        binding,
+        resource: BindingResource::Buffer(BufferBinding {
+            buffer,
+            offset: 0,
+            size: None,
+        }),
+    };
+    render_device.create_bind_group(&BindGroupDescriptor {
+        label: Some("mold_occupancy_bg"),
+        layout,
+        entries: &[
+            buffer(0, agent_buffer),
+            buffer(1, move_buffer),
+            buffer(2, occupancy_buffer),
+            buffer(3, settings_buffer),
+            buffer(4, deposit_buffer),
+            BindGroupEntry {
+                binding: 5,
+                resource: BindingResource::TextureView(obstacle_view),
+            },
+        ],
+    })
+}
+
+fn lifecycle_bind_group(
+    render_device: &RenderDevice,
+    layout: &BindGroupLayout,
+    agent_buffer: &Buffer,
+    next_agent_buffer: &Buffer,
+    settings_buffer: &Buffer,
+) -> BindGroup {
+    let buffer = |binding, buffer| BindGroupEntry {
+        binding,
+        resource: BindingResource::Buffer(BufferBinding {
+            buffer,
+            offset: 0,
+            size: None,
+        }),
+    };
+    render_device.create_bind_group(&BindGroupDescriptor {
+        label: Some("mold_lifecycle_bg"),
+        layout,
+        entries: &[
+            buffer(0, agent_buffer),
+            buffer(1, next_agent_buffer),
+            buffer(2, settings_buffer),
+        ],
+    })
+}
+
+/// The `update` bind groups reading `trail_map_a` and `trail_map_b`.
+#[allow(clippy::too_many_arguments)]
+fn update_bind_groups(
+    render_device: &RenderDevice,
+    layout: &BindGroupLayout,
+    agent_buffer: &Buffer,
+    settings_buffer: &Buffer,
+    trail_views: &[TextureView; 2],
+    deposit_buffer: &Buffer,
+    obstacle_view: &TextureView,
+    food_buffer: &Buffer,
+    interactions_buffer: &Buffer,
+    occupancy_buffer: &Buffer,
+    move_buffer: &Buffer,
+) -> [BindGroup; 2] {
+    let bind_group = |label, trail_view| {
+        render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: agent_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: settings_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(trail_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: deposit_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(obstacle_view),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: food_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: interactions_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: occupancy_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 8,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: move_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+            ],
+        })
+    };
+    [
+        bind_group("mold_update_bg_a", &trail_views[0]),
+        bind_group("mold_update_bg_b", &trail_views[1]),
+    ]
+}
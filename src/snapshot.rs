@@ -0,0 +1,542 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem::size_of,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    ecs::event::Events,
+    prelude::*,
+    render::{
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageCopyTexture,
+            ImageDataLayout, MapMode, Origin3d, TextureAspect,
+        },
+        renderer::{RenderContext, RenderDevice},
+    },
+};
+use bytemuck::Pod;
+use rand::Rng;
+
+use crate::{
+    div_ceil, population::PopulationRng, shaders::padded_bytes_per_row, Agent, Flow,
+    GlobalSettings, Kernel, MoldConfig, MoldShaders, Obstacles, SpeciesDisplaySettings,
+    SpeciesInteractions, SpeciesSettings,
+};
+
+const MAGIC: &[u8; 8] = b"MOLDSNAP";
+const VERSION: u32 = 6;
+/// Bytes per texel of the `Rgba16Float` trail maps.
+const TRAIL_TEXEL_SIZE: u32 = 8;
+
+/// The full state of a GPU simulation: enough to continue exactly where it
+/// was saved.
+///
+/// On disk this is `MOLDSNAP`, the format version and then, little endian and
+/// length prefixed, the config as RON, the step counter, which trail map is
+/// read next, the population seed, the agents, both trail maps, the obstacles,
+/// the food and the flow image.
+///
+/// Everything the config loads from images is stored, so a snapshot restores
+/// without them. Only agents added at runtime with an image `SpawnPattern`
+/// still need its image.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub config: MoldConfig,
+    pub step: u64,
+    /// The trail map the next step reads from, 0 for `trail_map_a`.
+    pub read_map: u8,
+    /// Seeds the random placement of agents added at runtime. The running
+    /// simulation is reseeded with it when saving, so both carry on alike.
+    pub population_seed: u64,
+    pub agents: Vec<Agent>,
+    /// `trail_map_a` and `trail_map_b` as tightly packed `Rgba16Float`
+    /// texels, one layer of four species after the other.
+    pub trail_maps: [Vec<u8>; 2],
+    /// [`Obstacles::mask`], one byte per pixel.
+    pub obstacles: Vec<u8>,
+    /// Food left on each pixel, see [`FoodConfig::food_map`](crate::FoodConfig::food_map).
+    pub food: Vec<f32>,
+    /// The decoded `MoldConfig::flow_image`, see `FlowField::Image`. A single
+    /// still pixel without one.
+    pub flow_image: Vec<Vec2>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(PathBuf, io::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(path, e) => {
+                write!(f, "failed to access snapshot `{}`: {}", path.display(), e)
+            }
+            SnapshotError::Invalid(path, msg) => {
+                write!(f, "invalid snapshot `{}`: {}", path.display(), msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Snapshot {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let path = path.as_ref();
+        let io_err = |e| SnapshotError::Io(path.to_owned(), e);
+        let invalid = |msg: String| SnapshotError::Invalid(path.to_owned(), msg);
+
+        let mut reader = BufReader::new(File::open(path).map_err(io_err)?);
+        let config = read_header(&mut reader, path)?;
+        let step = read_u64(&mut reader).map_err(io_err)?;
+        let read_map = read_u64(&mut reader).map_err(io_err)?;
+        let population_seed = read_u64(&mut reader).map_err(io_err)?;
+        let agents = read_block(&mut reader).map_err(io_err)?;
+        let trail_maps = [
+            read_block(&mut reader).map_err(io_err)?,
+            read_block(&mut reader).map_err(io_err)?,
+        ];
+        let obstacles = read_block(&mut reader).map_err(io_err)?;
+        let food = read_block(&mut reader).map_err(io_err)?;
+        let flow_image = read_block(&mut reader).map_err(io_err)?;
+
+        if agents.len() != config.agent_count as usize * size_of::<Agent>() {
+            return Err(invalid(format!(
+                "expected {} agents, found {} bytes of agent data",
+                config.agent_count,
+                agents.len()
+            )));
+        }
+        let trail_len = trail_map_len(&config);
+        if trail_maps.iter().any(|map| map.len() != trail_len) {
+            return Err(invalid(format!(
+                "trail maps should be {} bytes for a {}x{} simulation with {} species",
+                trail_len,
+                config.width,
+                config.height,
+                config.species_count()
+            )));
+        }
+        if obstacles.len() != (config.width * config.height) as usize {
+            return Err(invalid(format!(
+                "obstacles should be {} bytes for a {}x{} simulation",
+                config.width * config.height,
+                config.width,
+                config.height
+            )));
+        }
+        if food.len() != (config.width * config.height) as usize * size_of::<f32>() {
+            return Err(invalid(format!(
+                "food should be {} bytes for a {}x{} simulation",
+                config.width * config.height * size_of::<f32>() as u32,
+                config.width,
+                config.height
+            )));
+        }
+        let flow_len = match config.flow_image {
+            Some(_) => (config.width * config.height) as usize,
+            None => 1,
+        };
+        if flow_image.len() != flow_len * size_of::<Vec2>() {
+            return Err(invalid(format!(
+                "flow image should be {} bytes",
+                flow_len * size_of::<Vec2>()
+            )));
+        }
+        if read_map > 1 {
+            return Err(invalid(format!("no trail map {}", read_map)));
+        }
+
+        Ok(Snapshot {
+            config,
+            step,
+            read_map: read_map as u8,
+            population_seed,
+            agents: vec_from_bytes(&agents),
+            trail_maps,
+            obstacles,
+            food: vec_from_bytes(&food),
+            flow_image: vec_from_bytes(&flow_image),
+        })
+    }
+
+    pub fn obstacles(&self) -> Obstacles {
+        Obstacles::from_mask(
+            self.config.width,
+            self.config.height,
+            self.obstacles.clone(),
+        )
+    }
+
+    /// Reads only the config stored at the start of a snapshot file.
+    pub fn load_config(path: impl AsRef<Path>) -> Result<MoldConfig, SnapshotError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| SnapshotError::Io(path.to_owned(), e))?;
+        read_header(&mut BufReader::new(file), path)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        let io_err = |e| SnapshotError::Io(path.to_owned(), e);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_err)?;
+        }
+
+        let mut writer = BufWriter::new(File::create(path).map_err(io_err)?);
+        writer.write_all(MAGIC).map_err(io_err)?;
+        writer.write_all(&VERSION.to_le_bytes()).map_err(io_err)?;
+        write_block(&mut writer, self.config.to_ron().as_bytes()).map_err(io_err)?;
+        writer.write_all(&self.step.to_le_bytes()).map_err(io_err)?;
+        writer
+            .write_all(&(self.read_map as u64).to_le_bytes())
+            .map_err(io_err)?;
+        writer
+            .write_all(&self.population_seed.to_le_bytes())
+            .map_err(io_err)?;
+        write_block(&mut writer, bytemuck::cast_slice(&self.agents)).map_err(io_err)?;
+        for map in &self.trail_maps {
+            write_block(&mut writer, map).map_err(io_err)?;
+        }
+        write_block(&mut writer, &self.obstacles).map_err(io_err)?;
+        write_block(&mut writer, bytemuck::cast_slice(&self.food)).map_err(io_err)?;
+        write_block(&mut writer, bytemuck::cast_slice(&self.flow_image)).map_err(io_err)?;
+        writer.flush().map_err(io_err)
+    }
+}
+
+/// Copies `bytes` into a `Vec<T>`, which unlike casting the slice does not
+/// need them to be aligned for `T`.
+fn vec_from_bytes<T: Pod>(bytes: &[u8]) -> Vec<T> {
+    let mut vec = vec![T::zeroed(); bytes.len() / size_of::<T>()];
+    bytemuck::cast_slice_mut(&mut vec).copy_from_slice(bytes);
+    vec
+}
+
+fn trail_map_len(config: &MoldConfig) -> usize {
+    (TRAIL_TEXEL_SIZE * config.width * config.height * div_ceil(config.species_count(), 4)) as usize
+}
+
+fn read_header(reader: &mut impl Read, path: &Path) -> Result<MoldConfig, SnapshotError> {
+    let io_err = |e| SnapshotError::Io(path.to_owned(), e);
+    let invalid = |msg: String| SnapshotError::Invalid(path.to_owned(), msg);
+
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic).map_err(io_err)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a mold snapshot".to_string()));
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version).map_err(io_err)?;
+    let version = u32::from_le_bytes(version);
+    if version != VERSION {
+        return Err(invalid(format!(
+            "format version {} is not supported, expected {}",
+            version, VERSION
+        )));
+    }
+
+    let ron = read_block(reader).map_err(io_err)?;
+    let config: MoldConfig = std::str::from_utf8(&ron)
+        .map_err(|e| e.to_string())
+        .and_then(|ron| ron::from_str(ron).map_err(|e| e.to_string()))
+        .map_err(|e| invalid(format!("bad config: {}", e)))?;
+    config
+        .validate_settings()
+        .map_err(|e| invalid(format!("bad config: {}", e)))?;
+    Ok(config)
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_block(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u64(reader)?;
+    let mut block = Vec::new();
+    reader.take(len).read_to_end(&mut block)?;
+    if block.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(block)
+}
+
+fn write_block(writer: &mut impl Write, block: &[u8]) -> io::Result<()> {
+    writer.write_all(&(block.len() as u64).to_le_bytes())?;
+    writer.write_all(block)
+}
+
+/// Writes a snapshot of the running GPU simulation to the path.
+pub struct SaveSnapshot(pub PathBuf);
+
+/// Replaces the running GPU simulation with the snapshot at the path.
+pub struct LoadSnapshot(pub PathBuf);
+
+/// Snapshot work for the render world, handed over during extraction.
+#[derive(Default)]
+pub(crate) struct SnapshotRequests {
+    pub(crate) save: Vec<PathBuf>,
+    pub(crate) load: Option<Arc<Snapshot>>,
+    /// What `save` records besides the GPU state, filled in by
+    /// `snapshot_state_system`.
+    pub(crate) state: Option<SnapshotState>,
+}
+
+pub(crate) struct SnapshotState {
+    /// The render world's config with the settings in use written back.
+    pub(crate) config: MoldConfig,
+    pub(crate) population_seed: u64,
+}
+
+/// Registers the snapshot events, unless another plugin already did.
+pub(crate) fn add_snapshot_events(app: &mut App) {
+    if !app.world.contains_resource::<Events<SaveSnapshot>>() {
+        app.add_event::<SaveSnapshot>().add_event::<LoadSnapshot>();
+    }
+}
+
+/// Where the save hotkey puts snapshots.
+pub(crate) fn default_snapshot_path() -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    PathBuf::from("snapshots").join(format!("mold_{}.snap", secs))
+}
+
+/// Loads requested snapshots and makes the main world's settings match them.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn snapshot_request_system(
+    mut saves: EventReader<SaveSnapshot>,
+    mut loads: EventReader<LoadSnapshot>,
+    mut requests: ResMut<SnapshotRequests>,
+    mut config: ResMut<MoldConfig>,
+    mut species: ResMut<SpeciesSettings>,
+    mut interactions: ResMut<SpeciesInteractions>,
+    mut species_display: ResMut<SpeciesDisplaySettings>,
+    mut global: ResMut<GlobalSettings>,
+    mut kernel: ResMut<Kernel>,
+    mut flow: ResMut<Flow>,
+    mut obstacles: ResMut<Obstacles>,
+) {
+    requests.save.extend(saves.iter().map(|e| e.0.clone()));
+    for LoadSnapshot(path) in loads.iter() {
+        match Snapshot::load(path) {
+            Ok(snapshot) => {
+                info!(
+                    "loaded snapshot {} at step {}",
+                    path.display(),
+                    snapshot.step
+                );
+                *config = snapshot.config.clone();
+                species.0 = config.species.iter().map(|s| s.settings).collect();
+                *interactions = SpeciesInteractions::from_config(&config);
+                species_display.0 = config.species.iter().map(|s| s.display).collect();
+                *global = config.global;
+                *kernel = config.kernel;
+                *flow = config.flow;
+                *obstacles = snapshot.obstacles();
+                requests.load = Some(Arc::new(snapshot));
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+pub(crate) fn snapshot_extract_system(
+    mut requests: ResMut<SnapshotRequests>,
+    mut commands: Commands,
+) {
+    commands.insert_resource(std::mem::take(&mut *requests));
+}
+
+/// Rebuilds `MoldShaders` from a loaded snapshot, before anything is uploaded
+/// to the old resources.
+pub(crate) fn snapshot_restore_system(world: &mut World) {
+    if let Some(snapshot) = world.resource_mut::<SnapshotRequests>().load.take() {
+        let shaders = MoldShaders::from_snapshot(world, &snapshot);
+        world.insert_resource(shaders);
+    }
+}
+
+/// Collects the settings in use for the snapshots saved this frame and
+/// reseeds `PopulationRng` with the seed they store. Runs after this frame's
+/// agent changes.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn snapshot_state_system(
+    mut requests: ResMut<SnapshotRequests>,
+    config: Res<MoldConfig>,
+    species: Res<SpeciesSettings>,
+    interactions: Res<SpeciesInteractions>,
+    species_display: Res<SpeciesDisplaySettings>,
+    global: Res<GlobalSettings>,
+    kernel: Res<Kernel>,
+    flow: Res<Flow>,
+    mut rng: ResMut<PopulationRng>,
+) {
+    if requests.save.is_empty() {
+        return;
+    }
+    let mut config = config.clone();
+    config.apply_settings(
+        &species,
+        &interactions,
+        &species_display,
+        *global,
+        *kernel,
+        *flow,
+    );
+    let population_seed = rng.0.gen();
+    *rng = PopulationRng::from_seed(population_seed);
+    requests.state = Some(SnapshotState {
+        config,
+        population_seed,
+    });
+}
+
+/// Copies of the simulation state recorded by `MoldNode`, read back once the
+/// commands have been submitted.
+pub(crate) struct SnapshotReadback {
+    paths: Vec<PathBuf>,
+    config: MoldConfig,
+    step: u64,
+    read_map: u8,
+    population_seed: u64,
+    agents: Buffer,
+    trail_maps: [Buffer; 2],
+    food: Buffer,
+    bytes_per_row: u32,
+    obstacles: Vec<u8>,
+    flow_image: Vec<Vec2>,
+}
+
+impl SnapshotReadback {
+    pub(crate) fn record(
+        render_context: &mut RenderContext,
+        shaders: &MoldShaders,
+        state: &SnapshotState,
+        obstacles: &Obstacles,
+        step: u64,
+        read_map: u8,
+        paths: Vec<PathBuf>,
+    ) -> Self {
+        let config = &state.config;
+        let render_device = &render_context.render_device;
+        let layers = div_ceil(config.species_count(), 4);
+        let bytes_per_row = padded_bytes_per_row(TRAIL_TEXEL_SIZE * config.width);
+        let agents_size = (config.agent_count as usize * size_of::<Agent>()) as u64;
+        let agents = render_device.create_buffer(&BufferDescriptor {
+            label: Some("snapshot_agents"),
+            size: agents_size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let food_size = (config.width * config.height) as u64 * size_of::<f32>() as u64;
+        let food = render_device.create_buffer(&BufferDescriptor {
+            label: Some("snapshot_food"),
+            size: food_size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let trail_map = |label| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: (bytes_per_row * config.height * layers) as u64,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        };
+        let trail_maps = [
+            trail_map("snapshot_trail_map_a"),
+            trail_map("snapshot_trail_map_b"),
+        ];
+
+        let encoder = &mut render_context.command_encoder;
+        encoder.copy_buffer_to_buffer(&shaders.agent_buffer, 0, &agents, 0, agents_size);
+        encoder.copy_buffer_to_buffer(&shaders.food_buffer, 0, &food, 0, food_size);
+        for (texture, buffer) in shaders.trail_maps.iter().zip(&trail_maps) {
+            encoder.copy_texture_to_buffer(
+                ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                ImageCopyBuffer {
+                    buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(bytes_per_row),
+                        rows_per_image: NonZeroU32::new(config.height),
+                    },
+                },
+                Extent3d {
+                    width: config.width,
+                    height: config.height,
+                    depth_or_array_layers: layers,
+                },
+            );
+        }
+
+        SnapshotReadback {
+            paths,
+            config: config.clone(),
+            step,
+            read_map,
+            population_seed: state.population_seed,
+            agents,
+            trail_maps,
+            food,
+            bytes_per_row,
+            obstacles: obstacles.mask().to_vec(),
+            flow_image: shaders.flow_image.clone(),
+        }
+    }
+
+    pub(crate) fn finish(self, render_device: &RenderDevice) -> Result<(), SnapshotError> {
+        let read = |buffer: &Buffer| {
+            let slice = buffer.slice(..);
+            render_device.map_buffer(&slice, MapMode::Read);
+            let data = slice.get_mapped_range().to_vec();
+            buffer.unmap();
+            data
+        };
+        let row = (TRAIL_TEXEL_SIZE * self.config.width) as usize;
+        let unpad = |buffer: &Buffer| {
+            read(buffer)
+                .chunks(self.bytes_per_row as usize)
+                .flat_map(|padded| &padded[..row])
+                .copied()
+                .collect::<Vec<u8>>()
+        };
+
+        let snapshot = Snapshot {
+            agents: vec_from_bytes(&read(&self.agents)),
+            trail_maps: [unpad(&self.trail_maps[0]), unpad(&self.trail_maps[1])],
+            config: self.config,
+            step: self.step,
+            read_map: self.read_map,
+            population_seed: self.population_seed,
+            obstacles: self.obstacles,
+            food: vec_from_bytes(&read(&self.food)),
+            flow_image: self.flow_image,
+        };
+        for path in &self.paths {
+            snapshot.save(path)?;
+            info!(
+                "saved snapshot {} at step {}",
+                path.display(),
+                snapshot.step
+            );
+        }
+        Ok(())
+    }
+}
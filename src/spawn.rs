@@ -0,0 +1,309 @@
+use std::{
+    f32::consts::{PI, TAU},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bevy::math::{UVec2, Vec2, Vec3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::div_ceil;
+
+/// How one species' agents are placed when the simulation starts.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpawnConfig {
+    #[serde(default)]
+    pub pattern: SpawnPattern,
+    #[serde(default)]
+    pub heading: Heading,
+    #[serde(default)]
+    pub region: Region,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum SpawnPattern {
+    /// A filled disc, 20 pixels inside the region's shorter side.
+    #[default]
+    Disc,
+    /// Uniformly random over the whole region.
+    Uniform,
+    /// A band along the edge of the disc, `thickness` as a fraction of its radius.
+    Ring { thickness: f32 },
+    /// An even grid over the region.
+    Grid,
+    /// `count` discs at random places in the region, `radius` as a fraction
+    /// of the region's shorter side.
+    Clusters { count: u32, radius: f32 },
+    /// A spiral out from the region's centre.
+    Spiral { turns: f32 },
+    /// `count` evenly spaced horizontal lines, or vertical ones.
+    Lines { count: u32, vertical: bool },
+    /// Everything in the region's centre.
+    Point,
+    /// Random places with a probability proportional to a channel of an
+    /// image, which is stretched over the region.
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        channel: ImageChannel,
+    },
+}
+
+/// What counts as bright in a spawn image. Giving each species a different
+/// channel or palette colour of the same image splits it between them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ImageChannel {
+    #[default]
+    Luma,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    /// Pixels within `tolerance` of `color`, all with the same weight.
+    Color {
+        color: Vec3,
+        tolerance: f32,
+    },
+}
+
+/// Spawn images decoded by `validate`, so agents added at runtime don't
+/// decode them again.
+static IMAGE_CACHE: Mutex<Vec<CachedImage>> = Mutex::new(Vec::new());
+
+type CachedImage = (PathBuf, ImageChannel, Arc<ImageWeights>);
+
+/// The size of a spawn image and the running total of its per-pixel
+/// weights, row by row from the top.
+type ImageWeights = (UVec2, Vec<f64>);
+
+/// Which way agents face when they spawn, relative to the centre of the
+/// pattern (or of their cluster).
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum Heading {
+    Random,
+    #[default]
+    Inward,
+    Outward,
+    /// Counter-clockwise around the centre.
+    Tangential,
+}
+
+/// Part of the map as fractions of its size, `(0, 0)` to `(1, 1)` is all of it.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Region {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Default for Region {
+    fn default() -> Self {
+        Region {
+            min: Vec2::ZERO,
+            max: Vec2::ONE,
+        }
+    }
+}
+
+impl SpawnConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_settings()?;
+        match &self.pattern {
+            SpawnPattern::Image { path, channel } => {
+                Self::image_weights(path, *channel).map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// `validate` without the image.
+    pub(crate) fn validate_settings(&self) -> Result<(), String> {
+        let Region { min, max } = self.region;
+        if !(min.cmpge(Vec2::ZERO).all() && max.cmple(Vec2::ONE).all() && min.cmplt(max).all()) {
+            return Err(format!(
+                "`region` must lie within (0, 0) to (1, 1) with `min` below `max`, got {} to {}",
+                min, max
+            ));
+        }
+        match &self.pattern {
+            SpawnPattern::Clusters { count: 0, .. } | SpawnPattern::Lines { count: 0, .. } => {
+                Err("pattern `count` must be at least 1".into())
+            }
+            SpawnPattern::Ring { thickness } if !(0.0..=1.0).contains(thickness) => Err(format!(
+                "ring `thickness` must be between 0 and 1, got {}",
+                thickness
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Places `count` agents on a map of `size` pixels, returning their
+    /// positions and angles.
+    pub fn spawn(&self, rng: &mut impl Rng, size: UVec2, count: u32) -> Vec<(Vec2, f32)> {
+        let min = self.region.min * size.as_vec2();
+        let extent = (self.region.max - self.region.min) * size.as_vec2();
+        let center = min + extent / 2.;
+        let radius = (extent.min_element() / 2. - 20.).max(0.);
+
+        let placed: Vec<(Vec2, Vec2)> = match &self.pattern {
+            SpawnPattern::Disc => (0..count)
+                .map(|_| (center + random_in_disc(rng, radius), center))
+                .collect(),
+            SpawnPattern::Uniform => (0..count)
+                .map(|_| (min + random_unit(rng) * extent, center))
+                .collect(),
+            SpawnPattern::Ring { thickness } => (0..count)
+                .map(|_| {
+                    let r = radius * (1. - thickness * rng.gen_range(0.0..1.0));
+                    (center + direction(rng.gen_range(-PI..PI)) * r, center)
+                })
+                .collect(),
+            SpawnPattern::Grid => {
+                let cols = f32::ceil(f32::sqrt(count as f32 * extent.x / extent.y)).max(1.) as u32;
+                let rows = div_ceil(count, cols);
+                let cell = extent / Vec2::new(cols as f32, rows as f32);
+                (0..count)
+                    .map(|i| {
+                        let at = Vec2::new((i % cols) as f32, (i / cols) as f32) + 0.5;
+                        (min + at * cell, center)
+                    })
+                    .collect()
+            }
+            &SpawnPattern::Clusters {
+                count: clusters,
+                radius: cluster_radius,
+            } => {
+                let r = cluster_radius * extent.min_element();
+                let centers = (0..clusters)
+                    .map(|_| {
+                        let inset = Vec2::splat(r).min(extent / 2.);
+                        min + inset + random_unit(rng) * (extent - 2. * inset)
+                    })
+                    .collect::<Vec<_>>();
+                (0..count)
+                    .map(|i| {
+                        let c = centers[(i % clusters) as usize];
+                        (c + random_in_disc(rng, r), c)
+                    })
+                    .collect()
+            }
+            SpawnPattern::Spiral { turns } => (0..count)
+                .map(|i| {
+                    let t = (i as f32 + 0.5) / count as f32;
+                    let jitter = random_in_disc(rng, 1.);
+                    let along = direction(t * turns * TAU) * t * radius;
+                    (center + along + jitter, center)
+                })
+                .collect(),
+            &SpawnPattern::Lines {
+                count: lines,
+                vertical,
+            } => (0..count)
+                .map(|i| {
+                    let across = ((i % lines) as f32 + 0.5) / lines as f32;
+                    let along = rng.gen_range(0.0..1.0);
+                    let at = if vertical {
+                        Vec2::new(across, along)
+                    } else {
+                        Vec2::new(along, across)
+                    };
+                    (min + at * extent, center)
+                })
+                .collect(),
+            SpawnPattern::Point => (0..count).map(|_| (center, center)).collect(),
+            SpawnPattern::Image { path, channel } => {
+                // Checked by `validate`, or by `apply_agent_change` for
+                // configs restored from a snapshot.
+                let weights = Self::image_weights(path, *channel).unwrap();
+                let (dim, cumulative) = &*weights;
+                let total = *cumulative.last().unwrap();
+                (0..count)
+                    .map(|_| {
+                        let pick = rng.gen_range(0.0..total);
+                        let i = cumulative.partition_point(|&c| c <= pick) as u32;
+                        let texel = Vec2::new((i % dim.x) as f32, (i / dim.x) as f32);
+                        let at = (texel + random_unit(rng)) / dim.as_vec2();
+                        (min + at * extent, center)
+                    })
+                    .collect()
+            }
+        };
+
+        let max = size.as_vec2() - 0.5;
+        placed
+            .into_iter()
+            .map(|(pos, center)| {
+                let inward = center - pos;
+                let inward = f32::atan2(inward.y, inward.x);
+                let angle = match self.heading {
+                    Heading::Random => rng.gen_range(-PI..PI),
+                    Heading::Inward => inward,
+                    Heading::Outward => inward + PI,
+                    Heading::Tangential => inward - PI / 2.,
+                };
+                (pos.clamp(Vec2::ZERO, max), angle)
+            })
+            .collect()
+    }
+
+    /// The weights of the image at `path`, decoded on first use.
+    fn image_weights(path: &Path, channel: ImageChannel) -> Result<Arc<ImageWeights>, String> {
+        let mut cache = IMAGE_CACHE.lock().unwrap();
+        let cached = cache.iter().find(|(cached_path, cached_channel, _)| {
+            cached_path == path && *cached_channel == channel
+        });
+        if let Some((_, _, weights)) = cached {
+            return Ok(weights.clone());
+        }
+        let weights = Arc::new(decode_image_weights(path, channel)?);
+        cache.push((path.to_owned(), channel, weights.clone()));
+        Ok(weights)
+    }
+}
+
+/// Loads the image and returns its size and the running total of the
+/// per-pixel weights, row by row from the top.
+fn decode_image_weights(path: &Path, channel: ImageChannel) -> Result<ImageWeights, String> {
+    let image = image::open(path)
+        .map_err(|e| format!("failed to read spawn image `{}`: {}", path.display(), e))?
+        .to_rgba8();
+    let mut total = 0.0;
+    let cumulative = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0.map(|c| c as f32 / 255.);
+            total += match channel {
+                ImageChannel::Luma => 0.2126 * r + 0.7152 * g + 0.0722 * b,
+                ImageChannel::Red => r,
+                ImageChannel::Green => g,
+                ImageChannel::Blue => b,
+                ImageChannel::Alpha => a,
+                ImageChannel::Color { color, tolerance } => {
+                    (Vec3::new(r, g, b).distance(color) <= tolerance) as u8 as f32
+                }
+            } as f64;
+            total
+        })
+        .collect::<Vec<_>>();
+    if total <= 0.0 {
+        return Err(format!(
+            "spawn image `{}` has no bright pixels in {:?}",
+            path.display(),
+            channel
+        ));
+    }
+    Ok((UVec2::new(image.width(), image.height()), cumulative))
+}
+
+fn random_unit(rng: &mut impl Rng) -> Vec2 {
+    Vec2::new(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0))
+}
+
+fn random_in_disc(rng: &mut impl Rng, radius: f32) -> Vec2 {
+    let r = radius * f32::sqrt(rng.gen_range(0.0..1.0));
+    direction(rng.gen_range(-PI..PI)) * r
+}
+
+fn direction(angle: f32) -> Vec2 {
+    Vec2::new(f32::cos(angle), f32::sin(angle))
+}
@@ -0,0 +1,57 @@
+use bevy::math::{Vec2, Vec4};
+use bevy_compute::{Boundary, BrushStroke, CpuSimulation, MoldConfig};
+
+mod common;
+
+fn config(boundary: Boundary) -> MoldConfig {
+    let mut config = common::config(32, 24, 9).agents(300).species(2).build();
+    config.boundary = boundary;
+    config
+}
+
+#[test]
+fn agents_stay_on_the_map() {
+    for boundary in [Boundary::Clamp, Boundary::Wrap, Boundary::Reflect] {
+        let mut config = config(boundary);
+        for species in &mut config.species {
+            species.settings.move_speed = 200.0;
+        }
+        let mut sim = CpuSimulation::new(&config);
+        for _ in 0..100 {
+            sim.step();
+        }
+        for agent in sim.agents() {
+            let p = agent.position;
+            assert!(
+                p.cmpge(Vec2::ZERO).all() && p.cmple(Vec2::new(32.0, 24.0)).all(),
+                "{:?} let an agent reach {}",
+                boundary,
+                p
+            );
+            if boundary == Boundary::Wrap {
+                assert!(p.cmplt(Vec2::new(32.0, 24.0)).all(), "wrapped to {}", p);
+            }
+        }
+    }
+}
+
+#[test]
+fn wrap_diffuses_across_edges() {
+    let left_edge = |boundary| {
+        let mut config = config(boundary);
+        config.agent_count = 1;
+        let mut sim = CpuSimulation::new(&config);
+        sim.brush(&[BrushStroke {
+            from: Vec2::new(31.5, 0.0),
+            to: Vec2::new(31.5, 24.0),
+            radius: 0.5,
+            softness: 0.0,
+            amount: 1.0,
+            species: -1,
+        }]);
+        sim.step();
+        sim.trail()[12 * 32]
+    };
+    assert_eq!(left_edge(Boundary::Clamp), Vec4::ZERO);
+    assert!(left_edge(Boundary::Wrap).x > 0.0);
+}
@@ -0,0 +1,52 @@
+use bevy::math::{IVec2, UVec2, Vec2, Vec4};
+use bevy_compute::{window_to_texture, BrushStroke, CpuSimulation};
+
+mod common;
+
+#[test]
+fn window_corners_map_to_texture_corners() {
+    let texture = UVec2::new(200, 100);
+    // A window with a different aspect ratio than the trail map.
+    let window = Vec2::new(400., 400.);
+    assert_eq!(
+        window_to_texture(Vec2::new(0., 400.), window, texture),
+        Vec2::ZERO
+    );
+    assert_eq!(
+        window_to_texture(Vec2::new(400., 0.), window, texture),
+        Vec2::new(200., 100.)
+    );
+    assert_eq!(
+        window_to_texture(Vec2::new(100., 100.), window, texture),
+        Vec2::new(50., 75.)
+    );
+}
+
+#[test]
+fn paint_then_erase() {
+    let config = common::config(32, 16, 1).species(5).build();
+    let mut sim = CpuSimulation::new(&config);
+    let at = |sim: &CpuSimulation, p: IVec2, layer: i32| {
+        sim.trail()[((layer * 16 + p.y) * 32 + p.x) as usize]
+    };
+    let stroke = BrushStroke {
+        from: Vec2::new(4., 8.),
+        to: Vec2::new(12., 8.),
+        radius: 3.,
+        softness: 0.,
+        amount: 0.5,
+        species: 4,
+    };
+
+    sim.brush(&[stroke]);
+    assert_eq!(at(&sim, IVec2::new(8, 8), 1), Vec4::new(0.5, 0., 0., 0.));
+    assert_eq!(at(&sim, IVec2::new(8, 8), 0), Vec4::ZERO);
+    assert_eq!(at(&sim, IVec2::new(20, 8), 1), Vec4::ZERO);
+
+    sim.brush(&[BrushStroke {
+        amount: -1.,
+        species: -1,
+        ..stroke
+    }]);
+    assert!(sim.trail().iter().all(|&v| v == Vec4::ZERO));
+}
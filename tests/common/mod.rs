@@ -0,0 +1,59 @@
+//! Helpers shared by the integration tests, not every test uses all of them.
+#![allow(dead_code)]
+
+use bevy::math::Vec2;
+use bevy_compute::{Agent, MoldConfig};
+
+/// A small seeded `MoldConfig` for the CPU simulation, one agent of one
+/// species unless told otherwise.
+pub struct ConfigBuilder {
+    config: MoldConfig,
+    species: usize,
+}
+
+pub fn config(width: u32, height: u32, seed: u64) -> ConfigBuilder {
+    ConfigBuilder {
+        config: MoldConfig {
+            agent_count: 1,
+            width,
+            height,
+            seed: Some(seed),
+            ..Default::default()
+        },
+        species: 1,
+    }
+}
+
+impl ConfigBuilder {
+    pub fn agents(mut self, count: u32) -> Self {
+        self.config.agent_count = count;
+        self
+    }
+
+    pub fn species(mut self, count: usize) -> Self {
+        self.species = count;
+        self
+    }
+
+    /// Trail stays where it is put, without decaying or diffusing.
+    pub fn still(mut self) -> Self {
+        self.config.global.decay_rate = 0.0;
+        self.config.global.diffuse_rate = 0.0;
+        self
+    }
+
+    /// Keeps the first `species` species of the default config.
+    pub fn build(mut self) -> MoldConfig {
+        self.config.set_species_count(self.species);
+        self.config
+    }
+}
+
+/// An agent that takes part in nothing, for simulations that only need
+/// trail.
+pub fn dead() -> Agent {
+    Agent {
+        species: -1,
+        ..Agent::new(Vec2::ZERO, 0.0, 0)
+    }
+}
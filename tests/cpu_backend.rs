@@ -0,0 +1,61 @@
+use bevy::math::Vec2;
+use bevy_compute::{Agent, CpuSimulation, MoldConfig};
+
+mod common;
+
+fn small_config() -> MoldConfig {
+    common::config(64, 48, 7).agents(500).species(3).build()
+}
+
+#[test]
+fn same_seed_same_result() {
+    let config = small_config();
+    let mut a = CpuSimulation::new(&config);
+    let mut b = CpuSimulation::new(&config);
+    for _ in 0..20 {
+        a.step();
+        b.step();
+    }
+    assert_eq!(a.combine(), b.combine());
+}
+
+#[test]
+fn different_seed_different_result() {
+    let config = small_config();
+    let mut a = CpuSimulation::new(&config);
+    let mut b = CpuSimulation::new(&MoldConfig {
+        seed: Some(8),
+        ..config.clone()
+    });
+    for _ in 0..20 {
+        a.step();
+        b.step();
+    }
+    assert_ne!(a.combine(), b.combine());
+}
+
+#[test]
+fn agents_stay_on_the_map() {
+    let config = small_config();
+    let mut sim = CpuSimulation::new(&config);
+    for _ in 0..50 {
+        sim.step();
+    }
+    let size = Vec2::new(config.width as f32, config.height as f32);
+    for agent in sim.agents() {
+        assert!(agent.position.cmpge(Vec2::ZERO).all() && agent.position.cmple(size).all());
+    }
+}
+
+#[test]
+fn agent_deposits_on_its_own_layer() {
+    let config = small_config();
+    let agent = Agent::new(Vec2::new(20.5, 20.5), 0.0, 1);
+    let mut sim = CpuSimulation::with_agents(&config, vec![agent]);
+    sim.step();
+
+    let moved = sim.agents()[0].position.as_ivec2();
+    let texel = sim.trail()[(moved.y * config.width as i32 + moved.x) as usize];
+    assert!(texel.y > 0.0);
+    assert_eq!((texel.x, texel.z, texel.w), (0.0, 0.0, 0.0));
+}
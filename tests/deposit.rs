@@ -0,0 +1,128 @@
+use bevy::math::Vec2;
+use bevy_compute::{Agent, Boundary, CpuSimulation, Deposition, MoldConfig};
+
+mod common;
+
+fn config(agent_count: u32) -> MoldConfig {
+    let mut config = common::config(16, 16, 8)
+        .agents(agent_count)
+        .still()
+        .build();
+    config.species[0].settings.move_speed = 0.0;
+    config.species[0].settings.trail_weight = 5.0;
+    config.validate().unwrap();
+    config
+}
+
+#[test]
+fn deposits_on_the_same_pixel_add_up() {
+    let config = config(5);
+    let agents = (0..5)
+        .map(|i| Agent::new(Vec2::new(8.5, 8.5), i as f32, 0))
+        .collect();
+    let mut sim = CpuSimulation::with_agents(&config, agents);
+    sim.step();
+    // Five agents depositing 5.0 for 0.02s each.
+    let trail = sim.trail()[8 * 16 + 8].x;
+    assert!((trail - 0.5).abs() < 1e-3, "{}", trail);
+    let total: f32 = sim.trail().iter().map(|t| t.x).sum();
+    assert_eq!(total, trail);
+}
+
+#[test]
+fn dense_deposits_do_not_saturate() {
+    let config = config(20);
+    let agents = (0..20)
+        .map(|i| Agent::new(Vec2::new(8.5, 8.5), i as f32, 0))
+        .collect();
+    let mut sim = CpuSimulation::with_agents(&config, agents);
+    sim.step();
+    // Twenty agents depositing 5.0 for 0.02s each, well past 1.
+    let trail = sim.trail()[8 * 16 + 8].x;
+    assert!((trail - 2.0).abs() < 1e-3, "{}", trail);
+}
+
+#[test]
+fn deposits_of_a_partly_filled_layer_are_read() {
+    let mut config = config(1);
+    config.set_species_count(5);
+    let mut sim =
+        CpuSimulation::with_agents(&config, vec![Agent::new(Vec2::new(8.5, 8.5), 0.0, 4)]);
+    sim.step();
+    // The fifth species is alone in the second layer.
+    let texel = sim.trail()[16 * 16 + 8 * 16 + 8];
+    assert!(texel.x > 0.0);
+    assert_eq!(texel.y, 0.0);
+}
+
+#[test]
+fn bilinear_deposits_split_between_neighbours() {
+    let mut config = config(1);
+    config.deposition = Deposition::Bilinear;
+    let mut sim =
+        CpuSimulation::with_agents(&config, vec![Agent::new(Vec2::new(8.0, 8.0), 0.0, 0)]);
+    sim.step();
+    // On the corner between four pixels, each gets a quarter of 0.1.
+    for index in [7 * 16 + 7, 7 * 16 + 8, 8 * 16 + 7, 8 * 16 + 8] {
+        let trail = sim.trail()[index].x;
+        assert!((trail - 0.025).abs() < 1e-4, "{}", trail);
+    }
+    let total: f32 = sim.trail().iter().map(|t| t.x).sum();
+    assert!((total - 0.1).abs() < 1e-3, "{}", total);
+}
+
+#[test]
+fn swept_deposits_cover_the_whole_move() {
+    let mut config = config(1);
+    config.deposition = Deposition::Swept;
+    config.boundary = Boundary::Wrap;
+    config.species[0].settings.move_speed = 200.0;
+    config.species[0].settings.turn_speed = 0.0;
+    // Four pixels in one step, across the right edge.
+    let mut sim =
+        CpuSimulation::with_agents(&config, vec![Agent::new(Vec2::new(14.5, 8.5), 0.0, 0)]);
+    sim.step();
+    let row = &sim.trail()[8 * 16..9 * 16];
+    for x in [15, 0, 1, 2] {
+        assert!((row[x].x - 0.025).abs() < 1e-4, "{}: {}", x, row[x].x);
+    }
+    assert_eq!(row[14].x, 0.0);
+    let total: f32 = sim.trail().iter().map(|t| t.x).sum();
+    assert!((total - 0.1).abs() < 1e-3, "{}", total);
+}
+
+#[test]
+fn trail_weight_must_not_be_negative() {
+    let mut config = config(1);
+    config.species[0].settings.trail_weight = -1.0;
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn deposit_buffer_must_fit_a_storage_binding() {
+    let mut config = config(1);
+    config.width = 4096;
+    config.height = 4096;
+    config.set_species_count(2);
+    assert!(config.validate().is_ok());
+    config.set_species_count(3);
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn every_storage_buffer_must_fit_a_binding() {
+    // A 100 MB deposit buffer but a 200 MB occupancy buffer.
+    let mut config = config(1);
+    config.width = 5000;
+    config.height = 5000;
+    assert!(config.validate().is_ok());
+    config.occupancy = true;
+    assert!(config.validate().is_err());
+
+    // 24 bytes per agent.
+    config.occupancy = false;
+    config.agent_count = 6_000_000;
+    assert!(config.validate().is_err());
+    config.agent_count = 5_000_000;
+    assert!(config.validate().is_ok());
+}
@@ -0,0 +1,133 @@
+use bevy::math::Vec2;
+use bevy_compute::{Agent, Boundary, BrushStroke, CpuSimulation, Flow, FlowField, MoldConfig};
+
+mod common;
+use common::dead;
+
+fn config(field: FlowField) -> MoldConfig {
+    let mut config = common::config(32, 32, 4).still().build();
+    config.boundary = Boundary::Wrap;
+    config.flow = Flow {
+        field,
+        ..Default::default()
+    };
+    config.species[0].settings.move_speed = 0.0;
+    config.validate().unwrap();
+    config
+}
+
+#[test]
+fn agents_drift_with_the_flow() {
+    let wind = config(FlowField::Uniform {
+        velocity: Vec2::new(50.0, 0.0),
+    });
+    let mut sim = CpuSimulation::with_agents(&wind, vec![Agent::new(Vec2::new(4.5, 8.5), 0.0, 0)]);
+    for _ in 0..10 {
+        sim.step();
+    }
+    // 50 pixels per second for ten steps of 0.02s.
+    let position = sim.agents()[0].position;
+    assert!(
+        (position - Vec2::new(14.5, 8.5)).length() < 1e-3,
+        "{}",
+        position
+    );
+
+    let vortex = config(FlowField::Vortex {
+        center: Vec2::splat(0.5),
+        radius: 4.0,
+        speed: 10.0,
+    });
+    let mut sim =
+        CpuSimulation::with_agents(&vortex, vec![Agent::new(Vec2::new(20.0, 16.0), 0.0, 0)]);
+    sim.step();
+    // Right of the centre, at the radius, the flow points along +y.
+    let moved = sim.agents()[0].position - Vec2::new(20.0, 16.0);
+    assert!((moved - Vec2::new(0.0, 0.2)).length() < 1e-3, "{}", moved);
+
+    let noise = config(FlowField::CurlNoise {
+        scale: 8.0,
+        speed: 10.0,
+        evolution: 1.0,
+    });
+    let mut sim =
+        CpuSimulation::with_agents(&noise, vec![Agent::new(Vec2::new(10.3, 20.7), 0.0, 0)]);
+    sim.step();
+    let moved = sim.agents()[0].position - Vec2::new(10.3, 20.7);
+    assert!(moved.is_finite() && moved.length() > 0.0);
+}
+
+#[test]
+fn trail_is_carried_by_the_flow() {
+    let mut config = config(FlowField::Uniform {
+        velocity: Vec2::new(50.0, 0.0),
+    });
+    config.flow.agent_drift = 0.0;
+    let mut sim = CpuSimulation::with_agents(&config, vec![dead()]);
+    sim.brush(&[BrushStroke {
+        from: Vec2::new(8.5, 16.5),
+        to: Vec2::new(8.5, 16.5),
+        radius: 1.0,
+        softness: 0.0,
+        amount: 1.0,
+        species: 0,
+    }]);
+    for _ in 0..4 {
+        sim.step();
+    }
+    // One pixel per step, whole pixels move without smearing.
+    assert_eq!(sim.trail()[16 * 32 + 12].x, 1.0);
+    let total: f32 = sim.trail().iter().map(|t| t.x).sum();
+    assert_eq!(total, 1.0);
+}
+
+#[test]
+fn image_flow_needs_an_image() {
+    let mut config = config(FlowField::None);
+    config.flow.field = FlowField::Image { speed: 1.0 };
+    assert!(config.validate().is_err());
+    config.flow.field = FlowField::CurlNoise {
+        scale: 0.0,
+        speed: 1.0,
+        evolution: 0.0,
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn mid_grey_flow_image_is_still() {
+    let path = std::env::temp_dir().join(format!("mold_flow_{}.png", std::process::id()));
+    // Left half mid grey, right half full red.
+    image::RgbImage::from_fn(2, 1, |x, _| match x {
+        0 => image::Rgb([128, 128, 128]),
+        _ => image::Rgb([255, 128, 128]),
+    })
+    .save(&path)
+    .unwrap();
+    let mut config = config(FlowField::None);
+    config.flow.field = FlowField::Image { speed: 50.0 };
+    config.flow_image = Some(path.clone());
+    config.validate().unwrap();
+    let agents = vec![
+        Agent::new(Vec2::new(4.5, 8.5), 0.0, 0),
+        Agent::new(Vec2::new(20.5, 8.5), 0.0, 0),
+    ];
+    let mut sim = CpuSimulation::with_agents(&config, agents);
+    std::fs::remove_file(&path).unwrap();
+    sim.step();
+    assert_eq!(sim.agents()[0].position, Vec2::new(4.5, 8.5));
+    let moved = sim.agents()[1].position - Vec2::new(20.5, 8.5);
+    assert!((moved - Vec2::new(1.0, 0.0)).length() < 1e-5, "{}", moved);
+}
+
+#[test]
+fn undecodable_flow_image_is_rejected() {
+    let path = std::env::temp_dir().join(format!("mold_flow_broken_{}.png", std::process::id()));
+    std::fs::write(&path, b"not a png").unwrap();
+    let mut config = config(FlowField::None);
+    config.flow.field = FlowField::Image { speed: 1.0 };
+    config.flow_image = Some(path.clone());
+    let result = config.validate();
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
@@ -0,0 +1,58 @@
+use bevy::math::{UVec2, Vec2};
+use bevy_compute::{CpuSimulation, FoodConfig, FoodSource};
+
+mod common;
+
+fn food_config() -> FoodConfig {
+    FoodConfig {
+        image: None,
+        sources: vec![FoodSource {
+            position: Vec2::new(0.5, 0.5),
+            radius: 10.0,
+            amount: 1.0,
+        }],
+    }
+}
+
+#[test]
+fn sources_fill_discs() {
+    let food = food_config().food_map(UVec2::new(40, 20));
+    assert_eq!(food[10 * 40 + 20], 1.0);
+    assert_eq!(food[10 * 40 + 31], 0.0);
+    assert_eq!(food[0], 0.0);
+}
+
+#[test]
+fn only_hungry_species_eat() {
+    let mut config = common::config(40, 20, 2).agents(400).species(2).build();
+    config.food = food_config();
+    config.validate().unwrap();
+    let total = |sim: &CpuSimulation| sim.food().iter().sum::<f32>();
+
+    let mut sim = CpuSimulation::new(&config);
+    let start = total(&sim);
+    for _ in 0..50 {
+        sim.step();
+    }
+    assert_eq!(total(&sim), start);
+
+    config.species[1].settings.food_consumption = 5.0;
+    let mut sim = CpuSimulation::new(&config);
+    for _ in 0..50 {
+        sim.step();
+    }
+    assert!(total(&sim) < start);
+}
+
+#[test]
+fn undecodable_food_image_is_rejected() {
+    let path = std::env::temp_dir().join(format!("mold_food_broken_{}.png", std::process::id()));
+    std::fs::write(&path, b"not a png").unwrap();
+    let food = FoodConfig {
+        image: Some(path.clone()),
+        ..food_config()
+    };
+    let result = food.validate();
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
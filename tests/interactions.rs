@@ -0,0 +1,58 @@
+use bevy::math::Vec2;
+use bevy_compute::{Agent, BrushStroke, CpuSimulation, MoldConfig, SpeciesInteractions};
+
+mod common;
+
+fn config() -> MoldConfig {
+    common::config(64, 64, 3).species(2).build()
+}
+
+#[test]
+fn default_matrix_follows_self_and_avoids_others() {
+    let mut config = config();
+    config.species[1].settings.self_follow = 2.0;
+    let interactions = SpeciesInteractions::from_config(&config);
+    assert_eq!(interactions.get(0, 0), 4.0);
+    assert_eq!(interactions.get(1, 1), 2.0);
+    assert_eq!(interactions.get(0, 1), -1.0);
+
+    config.interactions = Some(vec![vec![1.0, 0.5], vec![0.0, 1.0]]);
+    config.set_species_count(3);
+    config.validate().unwrap();
+    assert_eq!(config.interaction(0, 1), 0.5);
+    assert_eq!(config.interaction(2, 2), 4.0);
+    assert_eq!(config.interaction(0, 2), -1.0);
+
+    config.interactions.as_mut().unwrap()[1].pop();
+    assert!(config.validate().is_err());
+}
+
+/// The heading of a species 0 agent after one step with species 1 trail
+/// under its left sensor.
+fn heading_near_other_species(weight: f32) -> f32 {
+    let mut config = config();
+    config.species[0].settings.move_speed = 0.0;
+    config.interactions = Some(vec![vec![1.0, weight], vec![-1.0, 1.0]]);
+    let agent = Agent::new(Vec2::new(20.0, 20.0), 0.0, 0);
+    let mut sim = CpuSimulation::with_agents(&config, vec![agent]);
+    let settings = config.species[0].settings;
+    let sensor_angle = settings.sensor_angle_degrees.to_radians();
+    let left =
+        agent.position + Vec2::new(sensor_angle.cos(), sensor_angle.sin()) * settings.sensor_offset;
+    sim.brush(&[BrushStroke {
+        from: left,
+        to: left,
+        radius: 4.0,
+        softness: 0.0,
+        amount: 1.0,
+        species: 1,
+    }]);
+    sim.step();
+    sim.agents()[0].angle
+}
+
+#[test]
+fn weights_pick_attraction_or_repulsion() {
+    assert!(heading_near_other_species(1.0) > 0.0);
+    assert!(heading_near_other_species(-1.0) < 0.0);
+}
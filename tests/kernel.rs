@@ -0,0 +1,118 @@
+use bevy::math::Vec2;
+use bevy_compute::{Boundary, BrushStroke, CpuSimulation, Kernel};
+
+mod common;
+use common::dead;
+
+/// A single species on a wrapping 32x32 map with one pixel of trail in the
+/// middle, diffusing fully every step and never decaying.
+fn pulse(kernel: Kernel) -> CpuSimulation {
+    let mut config = common::config(32, 32, 2).build();
+    config.boundary = Boundary::Wrap;
+    config.kernel = kernel;
+    config.global.decay_rate = 0.0;
+    config.global.diffuse_rate = 1000.0;
+    config.validate().unwrap();
+    let mut sim = CpuSimulation::with_agents(&config, vec![dead()]);
+    sim.brush(&[BrushStroke {
+        from: Vec2::new(16.5, 16.5),
+        to: Vec2::new(16.5, 16.5),
+        radius: 1.0,
+        softness: 0.0,
+        amount: 1.0,
+        species: 0,
+    }]);
+    sim
+}
+
+#[test]
+fn kernel_weights_are_normalized() {
+    let kernels = [
+        Kernel::Box { radius: 4 },
+        Kernel::Gaussian {
+            sigma: 2.0,
+            radius: None,
+        },
+        Kernel::Gaussian {
+            sigma: 10.0,
+            radius: Some(5),
+        },
+        Kernel::Elliptical {
+            sigma: Vec2::new(0.5, 6.0),
+            angle_degrees: 0.0,
+        },
+    ];
+    for kernel in kernels {
+        kernel.validate().unwrap();
+        let weights = kernel.weights();
+        for (radius, side) in [(weights.radius.x, weights.x), (weights.radius.y, weights.y)] {
+            let total = side[0] + 2.0 * side[1..=radius as usize].iter().sum::<f32>();
+            assert!((total - 1.0).abs() < 1e-5, "{:?} sums to {}", kernel, total);
+            assert!(side[radius as usize + 1..].iter().all(|w| *w == 0.0));
+        }
+    }
+    let gaussian = Kernel::Gaussian {
+        sigma: 2.0,
+        radius: None,
+    };
+    assert_eq!(gaussian.radius().x, 6);
+
+    assert!(Kernel::Box { radius: 40 }.validate().is_err());
+    assert!(Kernel::Gaussian {
+        sigma: 0.0,
+        radius: None
+    }
+    .validate()
+    .is_err());
+    assert!(!Kernel::default().is_separable());
+}
+
+#[test]
+fn separable_blur_keeps_the_total_trail() {
+    let mut sim = pulse(Kernel::Gaussian {
+        sigma: 3.0,
+        radius: None,
+    });
+    sim.step();
+    let total: f32 = sim.trail().iter().map(|t| t.x).sum();
+    assert!((total - 1.0).abs() < 0.01, "total trail is {}", total);
+    // Wider than the 3x3 box could spread in one step.
+    assert!(sim.trail()[16 * 32 + 20].x > 0.0);
+}
+
+#[test]
+fn elliptical_kernel_spreads_along_its_wide_axis() {
+    let mut sim = pulse(Kernel::Elliptical {
+        sigma: Vec2::new(4.0, 0.5),
+        angle_degrees: 0.0,
+    });
+    sim.step();
+    let trail = sim.trail();
+    assert!(trail[16 * 32 + 19].x > 0.0);
+    assert_eq!(trail[19 * 32 + 16].x, 0.0);
+    assert!(trail[16 * 32 + 17].x > trail[17 * 32 + 16].x);
+}
+
+#[test]
+fn turned_elliptical_kernel_spreads_along_the_diagonal() {
+    let kernel = Kernel::Elliptical {
+        sigma: Vec2::new(4.0, 0.5),
+        angle_degrees: 45.0,
+    };
+    assert!(!kernel.is_separable());
+    assert!(Kernel::Elliptical {
+        sigma: Vec2::ONE,
+        angle_degrees: f32::NAN,
+    }
+    .validate()
+    .is_err());
+    let mut sim = pulse(kernel);
+    sim.step();
+    let trail = sim.trail();
+    let total: f32 = trail.iter().map(|t| t.x).sum();
+    assert!((total - 1.0).abs() < 0.01, "total trail is {}", total);
+    assert!(trail[19 * 32 + 19].x > 0.0);
+    assert!(trail[13 * 32 + 13].x > 0.0);
+    assert_eq!(trail[13 * 32 + 19].x, 0.0);
+    assert!(trail[17 * 32 + 17].x > 20.0 * trail[16 * 32 + 18].x);
+}
@@ -0,0 +1,38 @@
+use bevy::math::Vec2;
+use bevy_compute::{Agent, CpuSimulation, MoldConfig};
+
+mod common;
+use common::dead;
+
+fn config() -> MoldConfig {
+    common::config(32, 32, 5).agents(4).build()
+}
+
+#[test]
+fn agents_die_of_old_age() {
+    let mut config = config();
+    config.species[0].settings.lifespan = config.fixed_delta_time * 3.5;
+    let mut sim = CpuSimulation::new(&config);
+    for _ in 0..3 {
+        sim.step();
+    }
+    assert!(sim.agents().iter().all(Agent::is_alive));
+    sim.step();
+    assert!(!sim.agents().iter().any(Agent::is_alive));
+}
+
+#[test]
+fn agents_split_into_dead_slots_sharing_energy() {
+    let mut config = config();
+    config.species[0].settings.split_energy = 1.0;
+    let parent = Agent {
+        energy: 2.0,
+        ..Agent::new(Vec2::new(16.0, 16.0), 0.0, 0)
+    };
+    let mut sim = CpuSimulation::with_agents(&config, vec![parent, dead(), dead(), dead()]);
+    for _ in 0..50 {
+        sim.step();
+    }
+    assert!(sim.agents().iter().all(Agent::is_alive));
+    assert!(sim.agents().iter().all(|agent| agent.energy == 0.5));
+}
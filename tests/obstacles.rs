@@ -0,0 +1,61 @@
+use bevy::math::Vec2;
+use bevy_compute::{CpuSimulation, Obstacles, Region, SpawnConfig, SpawnPattern};
+
+mod common;
+
+#[test]
+fn image_is_stretched_over_the_map() {
+    // White right half.
+    let path = std::env::temp_dir().join(format!("mold_obstacle_mask_{}.png", std::process::id()));
+    image::GrayImage::from_fn(2, 1, |x, _| image::Luma([if x == 1 { 255 } else { 0 }]))
+        .save(&path)
+        .unwrap();
+    let obstacles = Obstacles::from_image(&path, 8, 4).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    for y in 0..4 {
+        for x in 0..8 {
+            assert_eq!(obstacles.is_wall(x, y), x >= 4, "at {}, {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn wall_keeps_agents_and_trail_out() {
+    let mut config = common::config(64, 32, 5).agents(300).build();
+    config.species[0].spawn = SpawnConfig {
+        pattern: SpawnPattern::Uniform,
+        region: Region {
+            min: Vec2::ZERO,
+            max: Vec2::new(0.25, 1.0),
+        },
+        ..Default::default()
+    };
+    let mut sim = CpuSimulation::new(&config);
+    for y in 0..32 {
+        sim.obstacles.set_wall(20, y, true);
+    }
+
+    for _ in 0..200 {
+        sim.step();
+    }
+    assert!(sim.agents().iter().all(|a| a.position.x < 20.0));
+    for y in 0..32 {
+        for x in 20..64 {
+            assert_eq!(sim.trail()[y * 64 + x].x, 0.0, "trail at {}, {}", x, y);
+        }
+    }
+    assert!(sim.trail().iter().any(|v| v.x > 0.0));
+}
+
+#[test]
+fn undecodable_obstacle_image_is_rejected() {
+    let path =
+        std::env::temp_dir().join(format!("mold_obstacle_broken_{}.png", std::process::id()));
+    std::fs::write(&path, b"not a png").unwrap();
+    let mut config = common::config(8, 4, 1).build();
+    config.obstacles = Some(path.clone());
+    let result = config.validate();
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
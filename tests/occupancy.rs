@@ -0,0 +1,56 @@
+use std::collections::HashSet;
+
+use bevy::math::Vec2;
+use bevy_compute::{Agent, CpuSimulation, MoldConfig};
+
+mod common;
+
+fn config() -> MoldConfig {
+    let mut config = common::config(32, 32, 4).agents(2).species(2).build();
+    config.occupancy = true;
+    config
+}
+
+#[test]
+fn highest_index_wins_a_contested_cell() {
+    let mut config = config();
+    for species in &mut config.species {
+        species.settings.move_speed = 1.0 / config.fixed_delta_time;
+    }
+    let agents = vec![
+        Agent::new(Vec2::new(4.5, 5.5), 0.0, 0),
+        Agent::new(Vec2::new(6.5, 5.5), std::f32::consts::PI, 1),
+    ];
+    let mut sim = CpuSimulation::with_agents(&config, agents);
+    sim.step();
+    let cells = sim
+        .agents()
+        .iter()
+        .map(|agent| agent.position.as_ivec2().x)
+        .collect::<Vec<_>>();
+    assert_eq!(cells, [4, 5]);
+}
+
+#[test]
+fn agents_never_share_a_cell() {
+    let mut config = config();
+    for species in &mut config.species {
+        species.settings.move_speed = 40.0;
+    }
+    let agents = (0..256)
+        .map(|i| {
+            let position = Vec2::new((i % 16) as f32 * 2.0 + 0.5, (i / 16) as f32 * 2.0 + 0.5);
+            Agent::new(position, i as f32, i % 2)
+        })
+        .collect();
+    let mut sim = CpuSimulation::with_agents(&config, agents);
+    for _ in 0..100 {
+        sim.step();
+        let cells = sim
+            .agents()
+            .iter()
+            .map(|agent| agent.position.as_ivec2().to_array())
+            .collect::<HashSet<_>>();
+        assert_eq!(cells.len(), 256);
+    }
+}
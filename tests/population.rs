@@ -0,0 +1,71 @@
+use bevy_compute::{apply_agent_change, Agent, AgentChange, MoldConfig};
+use rand::{rngs::StdRng, SeedableRng};
+
+mod common;
+
+fn setup() -> (MoldConfig, Vec<Agent>) {
+    let config = common::config(64, 64, 5).agents(30).species(3).build();
+    let agents = Agent::spawn_initial(&config);
+    (config, agents)
+}
+
+fn count(agents: &[Agent], species: i32) -> usize {
+    agents.iter().filter(|a| a.species == species).count()
+}
+
+#[test]
+fn removing_a_species_keeps_the_others() {
+    let (config, mut agents) = setup();
+    let before = agents.clone();
+    let change = AgentChange::Remove {
+        species: Some(1),
+        count: 4,
+    };
+    apply_agent_change(&mut agents, &change, &config, &mut StdRng::seed_from_u64(0)).unwrap();
+
+    assert_eq!(
+        (count(&agents, 0), count(&agents, 1), count(&agents, 2)),
+        (10, 6, 10)
+    );
+    let kept = before.iter().filter(|a| a.species != 1);
+    for (a, b) in agents.iter().filter(|a| a.species != 1).zip(kept) {
+        assert_eq!((a.position, a.angle), (b.position, b.angle));
+    }
+}
+
+#[test]
+fn adding_appends_after_the_survivors() {
+    let (config, mut agents) = setup();
+    let before = agents.clone();
+    let change = AgentChange::Add {
+        species: None,
+        count: 7,
+    };
+    apply_agent_change(&mut agents, &change, &config, &mut StdRng::seed_from_u64(0)).unwrap();
+
+    assert_eq!(agents.len(), 37);
+    assert_eq!(
+        (count(&agents, 0), count(&agents, 1), count(&agents, 2)),
+        (13, 12, 12)
+    );
+    for (a, b) in agents.iter().zip(&before) {
+        assert_eq!((a.position, a.species), (b.position, b.species));
+    }
+}
+
+#[test]
+fn removing_everything_leaves_one_agent() {
+    let (config, mut agents) = setup();
+    let change = AgentChange::Remove {
+        species: None,
+        count: 1000,
+    };
+    apply_agent_change(&mut agents, &change, &config, &mut StdRng::seed_from_u64(0)).unwrap();
+    assert_eq!(agents.len(), 1);
+
+    let bad = AgentChange::Add {
+        species: Some(3),
+        count: 1,
+    };
+    assert!(apply_agent_change(&mut agents, &bad, &config, &mut StdRng::seed_from_u64(0)).is_err());
+}
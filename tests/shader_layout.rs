@@ -0,0 +1,210 @@
+use std::mem::{align_of, size_of};
+
+use bevy_compute::{
+    Agent, BrushStroke, DisplaySettings, FlowUniform, GlobalSettings, KernelWeights, PlainTime,
+    Settings,
+};
+use naga::{proc::Layouter, valid::Validator, Module, TypeInner};
+
+const SIMULATION: &str = include_str!("../assets/shaders/simulation.wgsl");
+const DISPLAY: &str = include_str!("../assets/shaders/display.wgsl");
+
+struct RustLayout {
+    fields: Vec<(&'static str, usize)>,
+    size: usize,
+    align: usize,
+}
+
+macro_rules! rust_layout {
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        RustLayout {
+            fields: vec![$((stringify!($field), std::mem::offset_of!($ty, $field))),*],
+            size: size_of::<$ty>(),
+            align: align_of::<$ty>(),
+        }
+    };
+}
+
+fn parse(source: &str) -> Module {
+    naga::front::wgsl::parse_str(source).unwrap_or_else(|e| panic!("{}", e.emit_to_string(source)))
+}
+
+fn check_layout(module: &Module, wgsl_name: &str, rust: RustLayout) {
+    let mut layouter = Layouter::default();
+    layouter.update(&module.types, &module.constants).unwrap();
+
+    let (handle, ty) = module
+        .types
+        .iter()
+        .find(|(_, ty)| ty.name.as_deref() == Some(wgsl_name))
+        .unwrap_or_else(|| panic!("no struct `{}` in the shader", wgsl_name));
+    let members = match &ty.inner {
+        TypeInner::Struct { members, .. } => members,
+        _ => panic!("`{}` is not a struct", wgsl_name),
+    };
+
+    let wgsl_fields = members
+        .iter()
+        .map(|m| (m.name.as_deref().unwrap(), m.offset as usize))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        wgsl_fields, rust.fields,
+        "field names or offsets of `{}` differ between WGSL and Rust",
+        wgsl_name
+    );
+
+    let layout = layouter[handle];
+    assert_eq!(
+        layout.size as usize, rust.size,
+        "size of `{}` differs between WGSL and Rust",
+        wgsl_name
+    );
+    // Rust may use a smaller alignment than WGSL (glam's Vec3 is 4-aligned),
+    // which is fine as long as arrays of the Rust struct keep the WGSL stride.
+    let wgsl_align = layout.alignment.get() as usize;
+    assert_eq!(
+        rust.size % wgsl_align,
+        0,
+        "`{}` is {} bytes, not a multiple of its WGSL alignment {}",
+        wgsl_name,
+        rust.size,
+        wgsl_align
+    );
+    assert!(
+        rust.align <= wgsl_align,
+        "`{}` is more strictly aligned in Rust ({}) than in WGSL ({})",
+        wgsl_name,
+        rust.align,
+        wgsl_align
+    );
+}
+
+#[test]
+fn shaders_validate() {
+    for source in [SIMULATION, DISPLAY] {
+        let module = parse(source);
+        Validator::new(Default::default(), Default::default())
+            .validate(&module)
+            .unwrap();
+    }
+}
+
+#[test]
+fn agent_layout() {
+    check_layout(
+        &parse(SIMULATION),
+        "Agent",
+        rust_layout!(Agent {
+            position,
+            angle,
+            species,
+            age,
+            energy
+        }),
+    );
+}
+
+#[test]
+fn settings_layout() {
+    check_layout(
+        &parse(SIMULATION),
+        "Settings",
+        rust_layout!(Settings {
+            trail_weight,
+            self_follow,
+            move_speed,
+            turn_speed,
+            sensor_angle_degrees,
+            sensor_offset,
+            sensor_size,
+            food_weight,
+            food_consumption,
+            lifespan,
+            energy_gain,
+            energy_cost,
+            split_energy,
+            decay_rate,
+            diffuse_rate,
+        }),
+    );
+}
+
+#[test]
+fn global_settings_layout() {
+    check_layout(
+        &parse(SIMULATION),
+        "GlobalSettings",
+        rust_layout!(GlobalSettings {
+            decay_rate,
+            diffuse_rate
+        }),
+    );
+}
+
+#[test]
+fn kernel_weights_layout() {
+    check_layout(
+        &parse(SIMULATION),
+        "KernelWeights",
+        rust_layout!(KernelWeights { radius, x, y, full }),
+    );
+}
+
+#[test]
+fn time_layout() {
+    check_layout(
+        &parse(SIMULATION),
+        "Time",
+        rust_layout!(PlainTime {
+            step,
+            seed,
+            delta,
+            boundary,
+            occupancy,
+            deposition
+        }),
+    );
+}
+
+#[test]
+fn flow_layout() {
+    check_layout(
+        &parse(SIMULATION),
+        "Flow",
+        rust_layout!(FlowUniform {
+            kind,
+            speed,
+            velocity,
+            center,
+            scale,
+            evolution,
+            agent_drift,
+            trail_advection
+        }),
+    );
+}
+
+#[test]
+fn display_settings_layout() {
+    check_layout(
+        &parse(SIMULATION),
+        "DispSettings",
+        rust_layout!(DisplaySettings { color, weight }),
+    );
+}
+
+#[test]
+fn brush_stroke_layout() {
+    check_layout(
+        &parse(SIMULATION),
+        "BrushStroke",
+        rust_layout!(BrushStroke {
+            from,
+            to,
+            radius,
+            softness,
+            amount,
+            species
+        }),
+    );
+}
@@ -0,0 +1,143 @@
+use bevy::math::Vec2;
+use bevy_compute::{
+    apply_agent_change, Agent, AgentChange, Flow, FlowField, GlobalSettings, ImageChannel, Kernel,
+    Snapshot, SnapshotError, SpawnPattern, SpeciesDisplaySettings, SpeciesInteractions,
+    SpeciesSettings,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+mod common;
+
+fn small_snapshot() -> Snapshot {
+    let config = common::config(16, 8, 3).agents(100).species(5).build();
+    let trail_len = 8 * 16 * 8 * 2;
+    Snapshot {
+        agents: Agent::spawn_initial(&config),
+        config,
+        step: 1234,
+        read_map: 1,
+        population_seed: 77,
+        trail_maps: [
+            (0..trail_len).map(|i| i as u8).collect(),
+            (0..trail_len).map(|i| (i * 7) as u8).collect(),
+        ],
+        obstacles: (0..16 * 8)
+            .map(|i| if i % 3 == 0 { 255 } else { 0 })
+            .collect(),
+        food: (0..16 * 8).map(|i| i as f32 / 8.0).collect(),
+        flow_image: vec![Vec2::ZERO],
+    }
+}
+
+#[test]
+fn round_trip() {
+    let path = std::env::temp_dir().join(format!("mold_round_trip_{}.snap", std::process::id()));
+    let snapshot = small_snapshot();
+    snapshot.save(&path).unwrap();
+    let loaded = Snapshot::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.config.to_ron(), snapshot.config.to_ron());
+    assert_eq!(
+        (loaded.step, loaded.read_map, loaded.population_seed),
+        (1234, 1, 77)
+    );
+    assert_eq!(
+        bytemuck::cast_slice::<_, u8>(&loaded.agents),
+        bytemuck::cast_slice::<_, u8>(&snapshot.agents)
+    );
+    assert_eq!(loaded.trail_maps, snapshot.trail_maps);
+    assert_eq!(loaded.obstacles, snapshot.obstacles);
+    assert_eq!(loaded.food, snapshot.food);
+    assert_eq!(loaded.flow_image, snapshot.flow_image);
+}
+
+#[test]
+fn restores_without_the_images_it_was_made_from() {
+    let path = std::env::temp_dir().join(format!("mold_moved_{}.snap", std::process::id()));
+    let gone = std::env::temp_dir().join(format!("mold_gone_{}.png", std::process::id()));
+    let mut snapshot = small_snapshot();
+    snapshot.config.obstacles = Some(gone.clone());
+    snapshot.config.food.image = Some(gone.clone());
+    snapshot.config.flow_image = Some(gone.clone());
+    snapshot.config.flow.field = FlowField::Image { speed: 1.0 };
+    snapshot.config.species[0].spawn.pattern = SpawnPattern::Image {
+        path: gone,
+        channel: ImageChannel::Luma,
+    };
+    snapshot.flow_image = (0..16 * 8).map(|i| Vec2::splat(i as f32)).collect();
+    snapshot.save(&path).unwrap();
+    let loaded = Snapshot::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded.flow_image, snapshot.flow_image);
+    // Only adding agents needs the spawn image.
+    let mut agents = loaded.agents.clone();
+    let change = AgentChange::Add {
+        species: Some(0),
+        count: 1,
+    };
+    let mut rng = StdRng::seed_from_u64(0);
+    assert!(apply_agent_change(&mut agents, &change, &loaded.config, &mut rng).is_err());
+}
+
+#[test]
+fn runtime_settings_survive_a_restore() {
+    let mut snapshot = small_snapshot();
+    let config = snapshot.config.clone();
+    let mut species = SpeciesSettings(config.species.iter().map(|s| s.settings).collect());
+    species.0[2].move_speed = 12.5;
+    let mut interactions = SpeciesInteractions::from_config(&config);
+    interactions.set(1, 3, 0.75);
+    let mut display = SpeciesDisplaySettings(config.species.iter().map(|s| s.display).collect());
+    display.0[4].weight = 0.5;
+    let global = GlobalSettings {
+        decay_rate: 0.25,
+        diffuse_rate: 7.0,
+    };
+    let kernel = Kernel::Gaussian {
+        sigma: 2.0,
+        radius: None,
+    };
+    let flow = Flow {
+        field: FlowField::Uniform {
+            velocity: Vec2::new(1.0, 2.0),
+        },
+        ..Default::default()
+    };
+    snapshot
+        .config
+        .apply_settings(&species, &interactions, &display, global, kernel, flow);
+
+    let path =
+        std::env::temp_dir().join(format!("mold_runtime_settings_{}.snap", std::process::id()));
+    snapshot.save(&path).unwrap();
+    let loaded = Snapshot::load(&path).unwrap().config;
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.species[2].settings.move_speed, 12.5);
+    assert_eq!(
+        SpeciesInteractions::from_config(&loaded).weights(),
+        interactions.weights()
+    );
+    assert_eq!(loaded.species[4].display.weight, 0.5);
+    assert_eq!(
+        (loaded.global.decay_rate, loaded.global.diffuse_rate),
+        (0.25, 7.0)
+    );
+    assert_eq!(loaded.kernel, kernel);
+    assert_eq!(loaded.flow, flow);
+}
+
+#[test]
+fn rejects_mismatched_trail_maps() {
+    let path = std::env::temp_dir().join(format!("mold_mismatched_{}.snap", std::process::id()));
+    let mut snapshot = small_snapshot();
+    snapshot.trail_maps[1].pop();
+    snapshot.save(&path).unwrap();
+    let loaded = Snapshot::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(loaded, Err(SnapshotError::Invalid(..))));
+}
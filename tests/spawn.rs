@@ -0,0 +1,178 @@
+use bevy::math::Vec2;
+use bevy_compute::{
+    apply_agent_change, Agent, AgentChange, Heading, ImageChannel, MoldConfig, Region, SpawnConfig,
+    SpawnPattern,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+mod common;
+
+fn config_with(spawns: Vec<SpawnConfig>) -> MoldConfig {
+    let mut config = common::config(200, 100, 1)
+        .agents(1001)
+        .species(spawns.len())
+        .build();
+    for (species, spawn) in config.species.iter_mut().zip(spawns) {
+        species.spawn = spawn;
+    }
+    config.validate().unwrap();
+    config
+}
+
+#[test]
+fn every_pattern_stays_in_its_region() {
+    let patterns = [
+        SpawnPattern::Disc,
+        SpawnPattern::Uniform,
+        SpawnPattern::Ring { thickness: 0.2 },
+        SpawnPattern::Grid,
+        SpawnPattern::Clusters {
+            count: 3,
+            radius: 0.1,
+        },
+        SpawnPattern::Spiral { turns: 3.0 },
+        SpawnPattern::Lines {
+            count: 4,
+            vertical: true,
+        },
+        SpawnPattern::Point,
+    ];
+    let region = Region {
+        min: Vec2::new(0.5, 0.0),
+        max: Vec2::new(1.0, 0.5),
+    };
+    for pattern in patterns {
+        let config = config_with(vec![SpawnConfig {
+            pattern: pattern.clone(),
+            heading: Heading::Random,
+            region,
+        }]);
+        let agents = Agent::spawn_initial(&config);
+        assert_eq!(agents.len(), 1001);
+        for agent in agents {
+            // The spiral jitters agents by up to a pixel.
+            assert!(
+                agent.position.cmpge(Vec2::new(99.0, -1.0)).all()
+                    && agent.position.cmple(Vec2::new(201.0, 51.0)).all(),
+                "{:?} placed an agent at {}",
+                pattern,
+                agent.position
+            );
+        }
+    }
+}
+
+#[test]
+fn species_use_their_own_spawn() {
+    let left = SpawnConfig {
+        pattern: SpawnPattern::Uniform,
+        heading: Heading::Outward,
+        region: Region {
+            min: Vec2::ZERO,
+            max: Vec2::new(0.5, 1.0),
+        },
+    };
+    let right = SpawnConfig {
+        pattern: SpawnPattern::Point,
+        heading: Heading::Inward,
+        region: Region {
+            min: Vec2::new(0.5, 0.0),
+            max: Vec2::ONE,
+        },
+    };
+    let agents = Agent::spawn_initial(&config_with(vec![left, right]));
+
+    assert_eq!(agents.iter().filter(|a| a.species == 0).count(), 501);
+    assert_eq!(agents.iter().filter(|a| a.species == 1).count(), 500);
+    for agent in agents {
+        match agent.species {
+            0 => assert!(agent.position.x <= 100.0),
+            _ => assert_eq!(agent.position, Vec2::new(150.0, 50.0)),
+        }
+    }
+}
+
+#[test]
+fn image_channels_split_species() {
+    // Left half red, top right green, bottom right black.
+    let path = std::env::temp_dir().join(format!("mold_spawn_mask_{}.png", std::process::id()));
+    let mask = image::RgbImage::from_fn(4, 2, |x, y| match (x < 2, y) {
+        (true, _) => image::Rgb([255, 0, 0]),
+        (false, 0) => image::Rgb([0, 255, 0]),
+        _ => image::Rgb([0, 0, 0]),
+    });
+    mask.save(&path).unwrap();
+
+    let spawn = |channel| SpawnConfig {
+        pattern: SpawnPattern::Image {
+            path: path.clone(),
+            channel,
+        },
+        ..Default::default()
+    };
+    let agents = Agent::spawn_initial(&config_with(vec![
+        spawn(ImageChannel::Red),
+        spawn(ImageChannel::Green),
+    ]));
+    std::fs::remove_file(&path).unwrap();
+
+    for agent in agents {
+        let p = agent.position;
+        match agent.species {
+            0 => assert!(p.x <= 100.0, "red agent at {}", p),
+            _ => assert!(p.x >= 100.0 && p.y <= 50.0, "green agent at {}", p),
+        }
+    }
+}
+
+#[test]
+fn runtime_adds_reuse_the_decoded_image() {
+    let path = std::env::temp_dir().join(format!("mold_spawn_reuse_{}.png", std::process::id()));
+    image::RgbImage::from_pixel(4, 2, image::Rgb([255, 255, 255]))
+        .save(&path)
+        .unwrap();
+    let config = config_with(vec![SpawnConfig {
+        pattern: SpawnPattern::Image {
+            path: path.clone(),
+            channel: ImageChannel::Luma,
+        },
+        ..Default::default()
+    }]);
+    std::fs::remove_file(&path).unwrap();
+
+    let mut agents = Agent::spawn_initial(&config);
+    let change = AgentChange::Add {
+        species: None,
+        count: 10,
+    };
+    apply_agent_change(&mut agents, &change, &config, &mut StdRng::seed_from_u64(0)).unwrap();
+    assert_eq!(agents.len(), 1011);
+}
+
+#[test]
+fn unusable_spawn_images_are_rejected() {
+    let dark = std::env::temp_dir().join(format!("mold_spawn_dark_{}.png", std::process::id()));
+    image::RgbImage::new(4, 2).save(&dark).unwrap();
+    let broken = std::env::temp_dir().join(format!("mold_spawn_broken_{}.png", std::process::id()));
+    std::fs::write(&broken, b"not a png").unwrap();
+
+    for path in [&dark, &broken] {
+        let mut config = MoldConfig::default();
+        config.species[0].spawn.pattern = SpawnPattern::Image {
+            path: path.clone(),
+            channel: ImageChannel::Luma,
+        };
+        assert!(config.validate().is_err(), "{}", path.display());
+    }
+    std::fs::remove_file(&dark).unwrap();
+    std::fs::remove_file(&broken).unwrap();
+}
+
+#[test]
+fn spawn_defaults_when_missing_from_ron() {
+    let config = MoldConfig::load(concat!(env!("CARGO_MANIFEST_DIR"), "/mold.ron")).unwrap();
+    assert!(config
+        .species
+        .iter()
+        .all(|s| matches!(s.spawn.pattern, SpawnPattern::Disc)));
+}
@@ -0,0 +1,33 @@
+use bevy::math::Vec2;
+use bevy_compute::{BrushStroke, CpuSimulation};
+
+mod common;
+use common::dead;
+
+#[test]
+fn species_decay_at_their_own_rate() {
+    let mut config = common::config(16, 16, 6).species(3).build();
+    config.global.decay_rate = 1.0;
+    config.global.diffuse_rate = 0.0;
+    config.species[0].settings.decay_rate = 0.0;
+    config.species[1].settings.decay_rate = 5.0;
+    config.species[1].settings.diffuse_rate = 0.0;
+    let mut sim = CpuSimulation::with_agents(&config, vec![dead()]);
+    sim.brush(&[BrushStroke {
+        from: Vec2::new(8.0, 8.0),
+        to: Vec2::new(8.0, 8.0),
+        radius: 20.0,
+        softness: 0.0,
+        amount: 1.0,
+        species: -1,
+    }]);
+    for _ in 0..10 {
+        sim.step();
+    }
+    // Ten steps of 0.02s take 0.2 off species 2, which uses the global rate.
+    let texel = sim.trail()[8 * 16 + 8];
+    assert_eq!(texel.x, 1.0);
+    assert_eq!(texel.y, 0.0);
+    assert!((texel.z - 0.8).abs() < 0.01);
+    assert!(sim.trail().iter().all(|t| t.x == 1.0 && t.y == 0.0));
+}